@@ -0,0 +1,280 @@
+/// What a command does, matched on by `update` to run it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Join,
+    Server,
+    Msg,
+    Me,
+    Shrug,
+    Nick,
+    Status,
+    Topic,
+    Upload,
+    Quit,
+    Help,
+}
+
+/// An argument of a command, named in its usage
+pub enum Arg {
+    /// One word, which can have spaces if quoted or escaped with `\`,
+    /// e.g. `"my file.png"` or `my\ file.png`
+    Word(&'static str),
+    OptionalWord(&'static str),
+    /// Everything left, spaces and newlines included
+    Text(&'static str),
+    OptionalText(&'static str),
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub args: &'static [Arg],
+    pub help: &'static str,
+}
+
+impl Command {
+    /// How the command is typed, e.g. `/msg <user> <text>`
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in self.args {
+            let arg = match arg {
+                Arg::Word(name) | Arg::Text(name) => format!(" <{}>", name),
+                Arg::OptionalWord(name) | Arg::OptionalText(name) => format!(" [{}]", name),
+            };
+            usage.push_str(&arg);
+        }
+        usage
+    }
+}
+
+/// Every command, in the order `/help` lists them. Adding one takes an
+/// entry here and an arm where `update` runs them.
+pub const COMMANDS: [Command; 11] = [
+    Command {
+        name: "join",
+        kind: Kind::Join,
+        args: &[Arg::Word("channel")],
+        help: "Opens a channel of this server",
+    },
+    Command {
+        name: "server",
+        kind: Kind::Server,
+        args: &[Arg::Text("name")],
+        help: "Opens a server",
+    },
+    Command {
+        name: "msg",
+        kind: Kind::Msg,
+        args: &[Arg::Word("user"), Arg::Text("text")],
+        help: "Sends a direct message",
+    },
+    Command {
+        name: "me",
+        kind: Kind::Me,
+        args: &[Arg::Text("action")],
+        help: "Sends an action, in italics",
+    },
+    Command {
+        name: "shrug",
+        kind: Kind::Shrug,
+        args: &[Arg::OptionalText("text")],
+        help: "Sends a shrug after the text",
+    },
+    Command {
+        name: "nick",
+        kind: Kind::Nick,
+        args: &[Arg::Text("nickname")],
+        help: "Sets our nickname in this server",
+    },
+    Command {
+        name: "status",
+        kind: Kind::Status,
+        args: &[Arg::OptionalText("text")],
+        help: "Sets our custom status, or clears it",
+    },
+    Command {
+        name: "topic",
+        kind: Kind::Topic,
+        args: &[Arg::OptionalText("topic")],
+        help: "Sets the topic of this channel, or clears it",
+    },
+    Command {
+        name: "upload",
+        kind: Kind::Upload,
+        args: &[Arg::OptionalWord("path"), Arg::OptionalText("caption")],
+        help: "Uploads a file, picked from a list if no path is given",
+    },
+    Command {
+        name: "quit",
+        kind: Kind::Quit,
+        args: &[],
+        help: "Quits",
+    },
+    Command {
+        name: "help",
+        kind: Kind::Help,
+        args: &[Arg::OptionalWord("command")],
+        help: "Lists the commands, or tells what one does",
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// A command as typed, with its arguments, optional ones being empty if
+/// left out
+pub struct Invocation {
+    pub kind: Kind,
+    pub args: Vec<String>,
+}
+
+/// Whether `input` is a command rather than a message; `//` starts a
+/// message with a `/`
+pub fn is_command(input: &str) -> bool {
+    input.starts_with('/') && !input.starts_with("//")
+}
+
+/// Parses a command line, the error being what to tell the user
+pub fn parse(line: &str) -> Result<Invocation, String> {
+    let line = line.strip_prefix('/').unwrap_or(line);
+    let (name, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let command = find(name).ok_or_else(|| format!("Unknown command /{}, see /help", name))?;
+    let usage = || format!("Usage: {}", command.usage());
+
+    let mut args = Vec::with_capacity(command.args.len());
+    for arg in command.args {
+        rest = rest.trim_start();
+        match arg {
+            Arg::Word(_) | Arg::OptionalWord(_) => {
+                let (word, after) = split_word(rest);
+                if word.is_empty() && matches!(arg, Arg::Word(_)) {
+                    return Err(usage());
+                }
+                args.push(word);
+                rest = after;
+            }
+            Arg::Text(_) | Arg::OptionalText(_) => {
+                if rest.trim().is_empty() && matches!(arg, Arg::Text(_)) {
+                    return Err(usage());
+                }
+                args.push(rest.trim_end().to_string());
+                rest = "";
+            }
+        }
+    }
+
+    if !rest.trim().is_empty() {
+        return Err(usage());
+    }
+
+    Ok(Invocation {
+        kind: command.kind,
+        args,
+    })
+}
+
+/// Splits the first word off `text`, taking quotes and `\` escapes away
+pub fn split_word(text: &str) -> (String, &str) {
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => word.extend(chars.next().map(|(_, escaped)| escaped)),
+            // only at the start, so that names like O'Neil are left alone
+            ('"' | '\'', None) if i == 0 => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            (c, None) if c.is_whitespace() => return (word, &text[i + c.len_utf8()..]),
+            (c, _) => word.push(c),
+        }
+    }
+
+    (word, "")
+}
+
+/// Escapes what `split_word` would take away or split at in `word`
+pub fn escape_word(word: &str) -> String {
+    let mut escaped = String::with_capacity(word.len());
+    for c in word.chars() {
+        if c.is_whitespace() || matches!(c, '\\' | '"' | '\'') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        match parse(line) {
+            Ok(invocation) => invocation.args,
+            Err(e) => panic!("{} did not parse: {}", line, e),
+        }
+    }
+
+    #[test]
+    fn parse_finds_the_command() {
+        assert!(matches!(
+            parse("/quit"),
+            Ok(Invocation {
+                kind: Kind::Quit,
+                ..
+            })
+        ));
+        assert_eq!(
+            parse("/nope").err().unwrap(),
+            "Unknown command /nope, see /help"
+        );
+    }
+
+    #[test]
+    fn parse_needs_required_words() {
+        assert_eq!(parse("/join").err().unwrap(), "Usage: /join <channel>");
+        assert_eq!(args("/join general"), ["general"]);
+        assert_eq!(args("/join   general  "), ["general"]);
+    }
+
+    #[test]
+    fn parse_leaves_optional_arguments_empty() {
+        assert_eq!(args("/help"), [""]);
+        assert_eq!(args("/help msg"), ["msg"]);
+        assert_eq!(args("/upload"), ["", ""]);
+        assert_eq!(args("/shrug"), [""]);
+    }
+
+    #[test]
+    fn parse_keeps_text_whole() {
+        assert_eq!(args("/msg bob hi  there\nbob"), ["bob", "hi  there\nbob"]);
+        assert_eq!(
+            parse("/msg bob").err().unwrap(),
+            "Usage: /msg <user> <text>"
+        );
+        assert_eq!(args("/upload cat.png a cat"), ["cat.png", "a cat"]);
+    }
+
+    #[test]
+    fn parse_refuses_extra_arguments() {
+        assert!(parse("/quit now").is_err());
+        assert!(parse("/join general random").is_err());
+    }
+
+    #[test]
+    fn words_can_have_spaces() {
+        assert_eq!(args(r"/upload my\ cat.png"), ["my cat.png", ""]);
+        assert_eq!(args(r#"/upload "my cat.png" hi"#), ["my cat.png", "hi"]);
+        assert_eq!(args("/msg O'Neil hi"), ["O'Neil", "hi"]);
+        assert_eq!(split_word(&escape_word(r#"a "b" \c"#)).0, r#"a "b" \c"#);
+    }
+
+    #[test]
+    fn double_slash_is_a_message() {
+        assert!(is_command("/help"));
+        assert!(!is_command("//help"));
+        assert!(!is_command("help"));
+    }
+}
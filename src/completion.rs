@@ -0,0 +1,152 @@
+use std::cmp::Reverse;
+
+/// How many candidates are kept, best first
+const MAX_CANDIDATES: usize = 50;
+
+/// Something the word before the cursor can be completed to
+pub struct Candidate {
+    /// What is shown in the list
+    pub label: String,
+    /// What replaces the word
+    pub replacement: String,
+    /// What it is matched against, without the sigil
+    pub name: String,
+}
+
+/// The completion list shown over the input box, which follows what is
+/// typed until a candidate is picked
+pub struct Completion {
+    /// Where the completed word starts in the input
+    pub start: usize,
+    /// The word, sigil included
+    pub word: String,
+    pub candidates: Vec<Candidate>,
+    pub selected: usize,
+}
+
+impl Completion {
+    pub fn select_by(&mut self, delta: isize) {
+        let len = self.candidates.len() as isize;
+        self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+    }
+}
+
+/// How well `query` matches `text`, if all its characters appear in
+/// order in it. Matches at the start and runs of consecutive characters
+/// score higher, gaps lower.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous = None;
+
+    for c in query.to_lowercase().chars() {
+        let found = next + text[next..].iter().position(|t| *t == c)?;
+        score -= (found - next) as i64;
+        if found == 0 {
+            score += 10;
+        }
+        if previous.map_or(false, |previous| previous + 1 == found) {
+            score += 5;
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+
+    // the closer to the whole name, the better
+    Some(score - ((text.len() - next) as i64 + 3) / 4)
+}
+
+/// Keeps the candidates matching `query`, best first; those whose
+/// replacement is in `recent`, most recent first, get ahead
+pub fn rank(query: &str, candidates: Vec<Candidate>, recent: &[String]) -> Vec<Candidate> {
+    let mut scored: Vec<(i64, Candidate)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let score = fuzzy_score(query, &candidate.name)?;
+            let recency = recent
+                .iter()
+                .position(|replacement| *replacement == candidate.replacement)
+                .map_or(0, |i| 20 - i.min(20) as i64);
+            Some((score + recency, candidate))
+        })
+        .collect();
+
+    scored.sort_by(|(a, first), (b, second)| {
+        (Reverse(a), &first.name).cmp(&(Reverse(b), &second.name))
+    });
+    scored.truncate(MAX_CANDIDATES);
+    scored.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str) -> Candidate {
+        Candidate {
+            label: name.to_string(),
+            replacement: format!("<{}>", name),
+            name: name.to_string(),
+        }
+    }
+
+    fn names(candidates: &[Candidate]) -> Vec<&str> {
+        candidates
+            .iter()
+            .map(|candidate| candidate.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_character_in_order() {
+        assert!(fuzzy_score("sml", "smile").is_some());
+        assert!(fuzzy_score("SMI", "smile").is_some());
+        assert!(fuzzy_score("ims", "smile").is_none());
+        assert!(fuzzy_score("smiles", "smile").is_none());
+        assert!(fuzzy_score("", "smile").is_some());
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_prefixes_and_runs() {
+        let prefix = fuzzy_score("gen", "general").unwrap();
+        let inner = fuzzy_score("gen", "dev-general").unwrap();
+        let scattered = fuzzy_score("gen", "good-enough").unwrap();
+        assert!(prefix > inner);
+        assert!(prefix > scattered);
+        // shorter names are closer to what was typed
+        assert!(fuzzy_score("smile", "smile") > fuzzy_score("smile", "smiley"));
+    }
+
+    #[test]
+    fn rank_drops_what_does_not_match() {
+        let ranked = rank("gen", vec![candidate("random"), candidate("general")], &[]);
+        assert_eq!(names(&ranked), ["general"]);
+    }
+
+    #[test]
+    fn rank_puts_recent_picks_first() {
+        let candidates = || vec![candidate("alexander"), candidate("alex")];
+        assert_eq!(names(&rank("al", candidates(), &[])), ["alex", "alexander"]);
+
+        let recent = ["<alexander>".to_string()];
+        assert_eq!(
+            names(&rank("al", candidates(), &recent)),
+            ["alexander", "alex"]
+        );
+    }
+
+    #[test]
+    fn select_by_wraps_around() {
+        let mut completion = Completion {
+            start: 0,
+            word: "@".to_string(),
+            candidates: vec![candidate("a"), candidate("b"), candidate("c")],
+            selected: 0,
+        };
+        completion.select_by(-1);
+        assert_eq!(completion.selected, 2);
+        completion.select_by(1);
+        assert_eq!(completion.selected, 0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use robespierre::robespierre_models::{
+    channels::Channel,
+    id::{ChannelId, UserId},
+    users::{Presence, User},
+};
+use tui::{
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::ListItem,
+};
+
+use crate::presence;
+
+/// A direct message conversation, with the other user in it
+struct Conversation {
+    channel: ChannelId,
+    user: UserId,
+    name: String,
+    online: bool,
+    presence: Option<Presence>,
+}
+
+/// Our direct message conversations, by the name of who they are with
+#[derive(Default)]
+pub struct DirectMessages {
+    conversations: Vec<Conversation>,
+}
+
+impl DirectMessages {
+    /// The active conversations among `channels`, `users` being the users
+    /// we know of, as sent with `Ready`
+    pub fn new(channels: &[Channel], users: &[User], me: Option<UserId>) -> Self {
+        let users: HashMap<UserId, &User> = users.iter().map(|user| (user.id, user)).collect();
+
+        let mut conversations: Vec<Conversation> = channels
+            .iter()
+            .filter_map(|channel| match channel {
+                Channel::DirectMessage {
+                    id,
+                    active: true,
+                    recipients,
+                    ..
+                } => {
+                    let user = recipients.iter().find(|user| Some(**user) != me)?;
+                    let known = users.get(user);
+                    Some(Conversation {
+                        channel: *id,
+                        user: *user,
+                        name: known.map_or_else(
+                            || "Unknown user".to_string(),
+                            |known| known.username.clone(),
+                        ),
+                        online: known.and_then(|known| known.online).unwrap_or(false),
+                        presence: known
+                            .and_then(|known| known.status.as_ref())
+                            .and_then(|status| status.presence),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        conversations.sort_by_cached_key(|conversation| conversation.name.to_lowercase());
+
+        Self { conversations }
+    }
+
+    /// Updates whether `user` is online, if we talk with them
+    pub fn set_online(&mut self, user: UserId, online: bool) {
+        for conversation in self.conversations.iter_mut().filter(|it| it.user == user) {
+            conversation.online = online;
+        }
+    }
+
+    /// Updates the presence `user` chose, if we talk with them
+    pub fn set_presence(&mut self, user: UserId, presence: Option<Presence>) {
+        for conversation in self.conversations.iter_mut().filter(|it| it.user == user) {
+            conversation.presence = presence;
+        }
+    }
+
+    /// The channel of the conversation at `index`
+    pub fn channel(&self, index: usize) -> Option<ChannelId> {
+        self.conversations
+            .get(index)
+            .map(|conversation| conversation.channel)
+    }
+
+    pub fn len(&self) -> usize {
+        self.conversations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conversations.is_empty()
+    }
+
+    /// One line per conversation, with the presence dot of the other user
+    pub fn items(&self) -> Vec<ListItem<'_>> {
+        self.conversations
+            .iter()
+            .map(|conversation| {
+                let dot = presence::color(conversation.online, conversation.presence);
+                let name_style = if conversation.online {
+                    Style::default()
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                };
+
+                ListItem::new(Spans::from(vec![
+                    Span::styled("● ", Style::default().fg(dot)),
+                    Span::styled(conversation.name.as_str(), name_style),
+                ]))
+            })
+            .collect()
+    }
+}
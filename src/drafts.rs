@@ -0,0 +1,61 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+use robespierre::robespierre_models::id::ChannelId;
+
+use crate::util;
+
+/// What was typed but not sent in each channel, kept on disk so it
+/// survives quitting or crashing.
+///
+/// The file is like the one of `History`: one draft per line, the channel
+/// id and the escaped text separated by a tab.
+pub struct Drafts {
+    path: PathBuf,
+    drafts: HashMap<ChannelId, String>,
+}
+
+impl Drafts {
+    /// Reads the drafts at `path`, which are empty if there is no such
+    /// file yet
+    pub fn load(path: PathBuf) -> Self {
+        let drafts = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (channel, text) = line.split_once('\t')?;
+                Some((channel.parse().ok()?, util::unescape_line(text)))
+            })
+            .collect();
+
+        Self { path, drafts }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut contents = String::new();
+        for (channel, text) in &self.drafts {
+            contents.push_str(&format!("{}\t{}\n", channel, util::escape_line(text)));
+        }
+        // a crash while saving must not lose every draft
+        util::write_atomically(&self.path, &contents)
+    }
+
+    /// Sets the draft of `channel`, removing it if `text` is blank, and
+    /// saves the drafts if that changed anything
+    pub fn set(&mut self, channel: ChannelId, text: &str) -> io::Result<()> {
+        if text.trim().is_empty() {
+            if self.drafts.remove(&channel).is_none() {
+                return Ok(());
+            }
+        } else if self.get(channel) != Some(text) {
+            self.drafts.insert(channel, text.to_string());
+        } else {
+            return Ok(());
+        }
+
+        self.save()
+    }
+
+    pub fn get(&self, channel: ChannelId) -> Option<&str> {
+        self.drafts.get(&channel).map(String::as_str)
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    ops::Deref,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process::Command,
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The text of an input box and the cursor in it, edited with emacs-like
+/// keys.
+///
+/// The cursor only ever stops between grapheme clusters, so it is never
+/// placed inside a combining sequence or an emoji.
+#[derive(Default)]
+pub struct Editor {
+    text: String,
+    /// Byte offset of the cursor in `text`
+    cursor: usize,
+    /// The text removed by the last kill, put back by yank
+    killed: String,
+}
+
+/// Lets the user edit `text` in `$VISUAL` or `$EDITOR` through a
+/// temporary file, and returns what they saved.
+///
+/// The terminal has to be out of raw mode and the alternate screen while
+/// the editor runs.
+pub fn edit_externally(text: &str) -> io::Result<String> {
+    let program = env::var("VISUAL")
+        .ok()
+        .filter(|program| !program.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|program| !program.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let (path, mut file) = create_temp_file()?;
+    let written = file.write_all(text.as_bytes());
+    drop(file);
+
+    // it can come with arguments, e.g. `code --wait`
+    let mut words = program.split_whitespace();
+    let edited = written
+        .and_then(|()| {
+            Command::new(words.next().unwrap_or("vi"))
+                .args(words)
+                .arg(&path)
+                .status()
+        })
+        .and_then(|status| {
+            if status.success() {
+                fs::read_to_string(&path)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("{} exited with {}", program, status),
+                ))
+            }
+        });
+    let _ = fs::remove_file(&path);
+
+    // editors end the file with a newline
+    edited.map(|text| text.trim_end_matches('\n').to_string())
+}
+
+/// Creates a file in the temporary directory that only we can read, with
+/// a name that cannot be guessed, so other users can neither read what is
+/// edited nor put their own file in its place.
+fn create_temp_file() -> io::Result<(PathBuf, File)> {
+    loop {
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let path = env::temp_dir().join(format!("tui-revolt-{}.md", suffix));
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl Deref for Editor {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Editor {
+    /// Replaces the text, with the cursor at the end
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// Takes the text out, leaving the editor empty
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    /// The columns between the start of the line the cursor is on and the
+    /// cursor
+    pub fn cursor_column(&self) -> usize {
+        let before = &self.text[..self.cursor];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        before[line_start..].width()
+    }
+
+    /// The line the cursor is on, counting from 0
+    pub fn cursor_line(&self) -> usize {
+        self.text[..self.cursor].matches('\n').count()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.matches('\n').count() + 1
+    }
+
+    /// The whitespace separated word that ends at the cursor, and where it
+    /// starts
+    pub fn word_before_cursor(&self) -> (usize, &str) {
+        let before = &self.text[..self.cursor];
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        (start, &before[start..])
+    }
+
+    /// Replaces the text between `start` and the cursor with `with`
+    pub fn replace_before_cursor(&mut self, start: usize, with: &str) {
+        self.text.replace_range(start..self.cursor, with);
+        self.cursor = start + with.len();
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+    }
+
+    fn previous_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    /// The start of the word before the cursor
+    fn previous_word(&self) -> usize {
+        let before = &self.text[..self.cursor];
+        let end = before.trim_end().len();
+        before[..end]
+            .unicode_word_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// The end of the word after the cursor
+    fn next_word(&self) -> usize {
+        self.text[self.cursor..]
+            .unicode_word_indices()
+            .next()
+            .map_or(self.text.len(), |(i, word)| self.cursor + i + word.len())
+    }
+
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    /// Moves the cursor to the same column of the line above or below,
+    /// returning false if there is no such line
+    fn move_line(&mut self, up: bool) -> bool {
+        let column = self.cursor_column();
+        let start = self.line_start();
+        let target = if up {
+            if start == 0 {
+                return false;
+            }
+            self.text[..start - 1].rfind('\n').map_or(0, |i| i + 1)
+        } else {
+            match self.text[self.cursor..].find('\n') {
+                Some(i) => self.cursor + i + 1,
+                None => return false,
+            }
+        };
+        let target_end = self.text[target..]
+            .find('\n')
+            .map_or(self.text.len(), |i| target + i);
+
+        self.cursor = target;
+        let mut width = 0;
+        for grapheme in self.text[target..target_end].graphemes(true) {
+            width += grapheme.width();
+            if width > column {
+                break;
+            }
+            self.cursor += grapheme.len();
+        }
+
+        true
+    }
+
+    /// Removes the text between the cursor and `to`, keeping it for yank
+    fn kill(&mut self, to: usize) {
+        let range = self.cursor.min(to)..self.cursor.max(to);
+        if !range.is_empty() {
+            self.killed = self.text[range.clone()].to_string();
+        }
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+    }
+
+    /// Removes the text between the cursor and `to`
+    fn delete(&mut self, to: usize) {
+        let range = self.cursor.min(to)..self.cursor.max(to);
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+    }
+
+    /// Handles the editing keys, returning whether `key` was one of them.
+    ///
+    /// Up and Down are only handled when there is a line to move to.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            // Enter sends, Shift-Enter cannot be told apart from it
+            Key::Alt('\r') | Key::Alt('\n') | Key::Ctrl('o') => self.insert('\n'),
+            Key::Char(c) => self.insert(c),
+            Key::Up => return self.move_line(true),
+            Key::Down => return self.move_line(false),
+            Key::Left | Key::Ctrl('b') => self.cursor = self.previous_boundary(),
+            Key::Right | Key::Ctrl('f') => self.cursor = self.next_boundary(),
+            Key::Alt('b') => self.cursor = self.previous_word(),
+            Key::Alt('f') => self.cursor = self.next_word(),
+            Key::Home | Key::Ctrl('a') => self.cursor = self.line_start(),
+            Key::End | Key::Ctrl('e') => self.cursor = self.line_end(),
+            Key::Backspace | Key::Ctrl('h') => self.delete(self.previous_boundary()),
+            Key::Delete | Key::Ctrl('d') => self.delete(self.next_boundary()),
+            Key::Ctrl('w') | Key::Alt('\x7f') => self.kill(self.previous_word()),
+            Key::Alt('d') => self.kill(self.next_word()),
+            Key::Ctrl('k') => self.kill(self.line_end()),
+            Key::Ctrl('u') => self.kill(self.line_start()),
+            Key::Ctrl('y') => {
+                let killed = self.killed.clone();
+                self.insert_str(&killed);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> Editor {
+        let mut editor = Editor::default();
+        editor.set(text.to_string());
+        editor
+    }
+
+    fn press(editor: &mut Editor, keys: &[Key]) {
+        for key in keys {
+            editor.handle_key(*key);
+        }
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let mut input = editor("hello, big  world");
+        press(&mut input, &[Key::Alt('b')]);
+        assert_eq!(input.cursor, "hello, big  ".len());
+        press(&mut input, &[Key::Alt('b'), Key::Alt('b')]);
+        assert_eq!(input.cursor, 0);
+        press(&mut input, &[Key::Alt('b')]);
+        assert_eq!(input.cursor, 0);
+
+        press(&mut input, &[Key::Alt('f')]);
+        assert_eq!(input.cursor, "hello".len());
+        press(&mut input, &[Key::Alt('f'), Key::Alt('f')]);
+        assert_eq!(input.cursor, input.len());
+    }
+
+    #[test]
+    fn kills_words_and_yanks_them_back() {
+        let mut input = editor("send this now");
+        press(&mut input, &[Key::Ctrl('w')]);
+        assert_eq!(&*input, "send this ");
+        press(&mut input, &[Key::Ctrl('a'), Key::Alt('d')]);
+        assert_eq!(&*input, " this ");
+        press(&mut input, &[Key::Ctrl('e'), Key::Ctrl('y')]);
+        assert_eq!(&*input, " this send");
+    }
+
+    #[test]
+    fn steps_over_whole_graphemes() {
+        let mut input = editor("e\u{301}👍🏽");
+        press(&mut input, &[Key::Left]);
+        assert_eq!(input.cursor, "e\u{301}".len());
+        press(&mut input, &[Key::Backspace]);
+        assert_eq!(&*input, "👍🏽");
+    }
+
+    #[test]
+    fn up_and_down_stop_at_the_first_and_last_lines() {
+        let mut input = editor("one\ntwo");
+        assert!(!input.handle_key(Key::Down));
+        assert!(input.handle_key(Key::Up));
+        assert_eq!(input.cursor_line(), 0);
+        assert!(!input.handle_key(Key::Up));
+    }
+}
@@ -0,0 +1,144 @@
+use robespierre::robespierre_models::{embeds::Embed, invites::RetrievedInvite};
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+/// Descriptions longer than this many lines are cut
+const MAX_DESCRIPTION_LINES: usize = 3;
+
+/// The prefixes invite links start with, followed by the invite code
+const INVITE_PREFIXES: [&str; 3] = [
+    "https://app.revolt.chat/invite/",
+    "https://rvlt.gg/",
+    "rvlt.gg/",
+];
+
+pub enum InviteState {
+    Loaded(RetrievedInvite),
+    Invalid,
+}
+
+/// The invite codes of the Revolt invite links in `text`
+pub fn invite_codes(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            INVITE_PREFIXES
+                .iter()
+                .find_map(|prefix| word.strip_prefix(prefix))
+        })
+        .map(|code| code.trim_end_matches(|c: char| !c.is_ascii_alphanumeric()))
+        .filter(|code| !code.is_empty())
+        .collect()
+}
+
+fn border_style(colour: Option<&str>) -> Style {
+    let color = colour
+        .and_then(crate::util::hex_color)
+        .unwrap_or(Color::DarkGray);
+
+    Style::default().fg(color)
+}
+
+/// Puts `lines` in a box open on the right, with rules fitting in `width` columns
+fn boxed(lines: Vec<Vec<Span<'static>>>, style: Style, width: usize) -> Vec<Spans<'static>> {
+    let rule = "─".repeat(width.saturating_sub(3).min(40));
+
+    let mut boxed = vec![Spans::from(Span::styled(format!("  ┌{}", rule), style))];
+    boxed.extend(lines.into_iter().map(|line| {
+        let mut spans = vec![Span::styled("  │ ", style)];
+        spans.extend(line);
+        Spans::from(spans)
+    }));
+    boxed.push(Spans::from(Span::styled(format!("  └{}", rule), style)));
+
+    boxed
+}
+
+/// The url of the image to preview under an embed, if it has one
+pub fn embed_image(embed: &Embed) -> Option<&str> {
+    match embed {
+        Embed::Website(website) => website.image.as_ref().map(|image| image.url.as_str()),
+        Embed::Image(image) => Some(image.url.as_str()),
+        Embed::None => None,
+    }
+}
+
+/// The lines an embed is drawn as under its message, without its image
+pub fn embed_lines(embed: &Embed, width: usize) -> Vec<Spans<'static>> {
+    match embed {
+        Embed::Website(website) => {
+            let mut lines = Vec::new();
+
+            if let Some(site_name) = &website.site_name {
+                lines.push(vec![Span::styled(
+                    site_name.clone(),
+                    Style::default().fg(Color::Gray),
+                )]);
+            }
+            if let Some(title) = &website.title {
+                lines.push(vec![Span::styled(
+                    title.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )]);
+            }
+            if let Some(description) = &website.description {
+                let description_lines = description.lines().filter(|it| !it.trim().is_empty());
+                for (i, line) in description_lines.enumerate() {
+                    if i == MAX_DESCRIPTION_LINES {
+                        lines.push(vec![Span::raw("…")]);
+                        break;
+                    }
+                    lines.push(vec![Span::raw(line.to_string())]);
+                }
+            }
+            if let Some(url) = &website.url {
+                lines.push(vec![Span::styled(
+                    url.clone(),
+                    Style::default()
+                        .fg(Color::LightBlue)
+                        .add_modifier(Modifier::UNDERLINED),
+                )]);
+            }
+
+            boxed(lines, border_style(website.colour.as_deref()), width)
+        }
+        Embed::Image(_) | Embed::None => Vec::new(),
+    }
+}
+
+/// The lines a Revolt invite link is drawn as under its message
+pub fn invite_lines(code: &str, invite: Option<&InviteState>, width: usize) -> Vec<Spans<'static>> {
+    let style = Style::default().fg(Color::LightMagenta);
+
+    let lines = match invite {
+        Some(InviteState::Loaded(invite)) => vec![
+            vec![Span::styled(
+                "Server invite",
+                Style::default().fg(Color::Gray),
+            )],
+            vec![Span::styled(
+                invite.server_name.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )],
+            vec![Span::raw(format!(
+                "#{} · {} members",
+                invite.channel_name, invite.member_count
+            ))],
+            vec![Span::styled(
+                "[Join from the message actions]",
+                Style::default().fg(Color::Green),
+            )],
+        ],
+        Some(InviteState::Invalid) => vec![vec![Span::styled(
+            format!("Invalid invite {}", code),
+            Style::default().fg(Color::Red),
+        )]],
+        None => vec![vec![Span::styled(
+            format!("Loading invite {}…", code),
+            Style::default().fg(Color::DarkGray),
+        )]],
+    };
+
+    boxed(lines, style, width)
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use image::{imageops::FilterType, RgbaImage};
+use termion::cursor;
+use tui::layout::Rect;
+
+use crate::images::ImageCache;
+
+/// Terminals are sent image data in chunks of at most this many bytes
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// The cell size assumed when the terminal does not report its size in pixels
+const FALLBACK_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// How images are drawn in the terminal
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    /// The kitty graphics protocol, also spoken by WezTerm and Konsole
+    Kitty,
+    Sixel,
+    /// Unicode half blocks, drawn by tui itself
+    Blocks,
+}
+
+impl GraphicsProtocol {
+    /// Guesses what the terminal supports from the environment.
+    ///
+    /// `$GRAPHICS` (`kitty`, `sixel` or `blocks`) takes precedence over
+    /// everything else.
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+
+        match var("GRAPHICS").as_str() {
+            "kitty" => return GraphicsProtocol::Kitty,
+            "sixel" => return GraphicsProtocol::Sixel,
+            "blocks" => return GraphicsProtocol::Blocks,
+            _ => {}
+        }
+
+        let term = var("TERM");
+        let term_program = var("TERM_PROGRAM");
+
+        if term.contains("kitty")
+            || std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term_program == "WezTerm"
+            || std::env::var_os("KONSOLE_VERSION").is_some()
+        {
+            GraphicsProtocol::Kitty
+        } else if term.contains("sixel")
+            || term == "foot"
+            || term.starts_with("mlterm")
+            || term_program == "mlterm"
+        {
+            GraphicsProtocol::Sixel
+        } else {
+            GraphicsProtocol::Blocks
+        }
+    }
+}
+
+/// An image to draw over the cells of `area`
+#[derive(Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub url: String,
+    pub area: Rect,
+}
+
+/// Draws images over what tui has drawn, with the kitty graphics
+/// protocol or sixels.
+pub struct Graphics {
+    pub protocol: GraphicsProtocol,
+    /// What is currently on screen, so nothing is sent if it did not change
+    last_placements: Vec<Placement>,
+    /// Kitty image ids of the images already sent to the terminal, by url
+    kitty_ids: HashMap<String, u32>,
+}
+
+impl Graphics {
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        Self {
+            protocol,
+            last_placements: Vec::new(),
+            kitty_ids: HashMap::new(),
+        }
+    }
+
+    /// Forgets what is on screen, after something else drew over it
+    pub fn forget_placements(&mut self) {
+        self.last_placements.clear();
+    }
+
+    /// Whether drawing `placements` would change what is on screen
+    pub fn changed(&self, placements: &[Placement]) -> bool {
+        self.last_placements != placements
+    }
+
+    /// Draws `placements` over the screen, replacing the previous ones.
+    ///
+    /// With sixels, the previous images cannot be removed, so the caller
+    /// has to redraw the whole screen before calling this if `changed`.
+    pub fn draw<W: Write>(
+        &mut self,
+        out: &mut W,
+        placements: Vec<Placement>,
+        images: &ImageCache,
+    ) -> io::Result<()> {
+        if !self.changed(&placements) {
+            return Ok(());
+        }
+
+        if self.protocol == GraphicsProtocol::Kitty {
+            // delete all placements, but keep the image data around
+            write!(out, "\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        }
+
+        for placement in &placements {
+            let image = match images.get(&placement.url) {
+                Some(image) => image,
+                None => continue,
+            };
+
+            write!(
+                out,
+                "{}{}",
+                cursor::Save,
+                cursor::Goto(placement.area.x + 1, placement.area.y + 1)
+            )?;
+
+            match self.protocol {
+                GraphicsProtocol::Kitty => self.draw_kitty(out, placement, image)?,
+                GraphicsProtocol::Sixel => {
+                    out.write_all(encode_sixel(image, placement.area).as_bytes())?
+                }
+                GraphicsProtocol::Blocks => {}
+            }
+
+            write!(out, "{}", cursor::Restore)?;
+        }
+
+        out.flush()?;
+        self.last_placements = placements;
+
+        Ok(())
+    }
+
+    fn draw_kitty<W: Write>(
+        &mut self,
+        out: &mut W,
+        placement: &Placement,
+        image: &RgbaImage,
+    ) -> io::Result<()> {
+        let next_id = self.kitty_ids.len() as u32 + 1;
+        let id = match self.kitty_ids.get(&placement.url) {
+            Some(id) => *id,
+            None => {
+                let (width, height) = image.dimensions();
+                let data = base64::encode(image.as_raw());
+                let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = (i + 1 < chunks.len()) as u8;
+                    if i == 0 {
+                        write!(
+                            out,
+                            "\x1b_Ga=t,f=32,s={},v={},i={},q=2,m={};",
+                            width, height, next_id, more
+                        )?;
+                    } else {
+                        write!(out, "\x1b_Gm={};", more)?;
+                    }
+                    out.write_all(chunk)?;
+                    write!(out, "\x1b\\")?;
+                }
+
+                self.kitty_ids.insert(placement.url.clone(), next_id);
+                next_id
+            }
+        };
+
+        write!(
+            out,
+            "\x1b_Ga=p,i={},c={},r={},C=1,q=2\x1b\\",
+            id, placement.area.width, placement.area.height
+        )
+    }
+}
+
+/// The size of a cell in pixels
+pub fn cell_size() -> (u32, u32) {
+    match (termion::terminal_size(), termion::terminal_size_pixels()) {
+        (Ok((cols, rows)), Ok((width, height))) if cols > 0 && rows > 0 && width > 0 => {
+            (width as u32 / cols as u32, height as u32 / rows as u32)
+        }
+        _ => FALLBACK_CELL_SIZE,
+    }
+}
+
+/// Encodes `image` as sixels, scaled to cover `area`, using the 6x6x6
+/// colour cube as the palette.
+fn encode_sixel(image: &RgbaImage, area: Rect) -> String {
+    let (cell_width, cell_height) = cell_size();
+    let image = image::imageops::resize(
+        image,
+        area.width as u32 * cell_width,
+        area.height as u32 * cell_height,
+        FilterType::Triangle,
+    );
+    let (width, height) = image.dimensions();
+
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let color_index = |x: u32, y: u32| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        if a < 128 {
+            None
+        } else {
+            Some(36 * level(r) + 6 * level(g) + level(b))
+        }
+    };
+
+    let mut out = String::from("\x1bPq");
+    for i in 0..216 {
+        let (r, g, b) = (i / 36, i / 6 % 6, i % 6);
+        let _ = write!(out, "#{};2;{};{};{}", i, r * 20, g * 20, b * 20);
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let indices: Vec<Vec<Option<usize>>> = (0..rows)
+            .map(|dy| (0..width).map(|x| color_index(x, band + dy)).collect())
+            .collect();
+
+        let mut used = [false; 216];
+        indices
+            .iter()
+            .flatten()
+            .flatten()
+            .for_each(|&i| used[i] = true);
+
+        for color in (0..216).filter(|&color| used[color]) {
+            let _ = write!(out, "#{}", color);
+
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width as usize {
+                let bits = (0..rows as usize)
+                    .filter(|&dy| indices[dy][x] == Some(color))
+                    .fold(0, |bits, dy| bits | 1 << dy);
+                let c = (63 + bits) as u8 as char;
+
+                run = match run {
+                    Some((run_char, len)) if run_char == c => Some((c, len + 1)),
+                    Some(previous) => {
+                        push_sixel_run(&mut out, previous);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some(run) = run {
+                push_sixel_run(&mut out, run);
+            }
+
+            // back to the start of the band, for the next colour
+            out.push('$');
+        }
+
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_sixel_run(out: &mut String, (c, len): (char, usize)) {
+    if len > 3 {
+        let _ = write!(out, "!{}{}", len, c);
+    } else {
+        out.extend(std::iter::repeat(c).take(len));
+    }
+}
@@ -0,0 +1,85 @@
+use std::{fs, io, path::PathBuf};
+
+use robespierre::robespierre_models::id::ChannelId;
+
+use crate::util;
+
+/// How many inputs are remembered, across all channels
+const MAX_ENTRIES: usize = 500;
+
+/// The inputs sent so far, in every channel, kept on disk between
+/// sessions.
+///
+/// The file has one entry per line, the channel id and the text separated
+/// by a tab, with backslashes and newlines escaped.
+pub struct History {
+    path: PathBuf,
+    /// Oldest first
+    entries: Vec<(ChannelId, String)>,
+}
+
+impl History {
+    /// Reads the history at `path`, which is empty if there is no such
+    /// file yet
+    pub fn load(path: PathBuf) -> Self {
+        let entries = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (channel, text) = line.split_once('\t')?;
+                Some((channel.parse().ok()?, util::unescape_line(text)))
+            })
+            .collect();
+
+        Self { path, entries }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut contents = String::new();
+        for (channel, text) in &self.entries {
+            contents.push_str(&format!("{}\t{}\n", channel, util::escape_line(text)));
+        }
+        util::write_atomically(&self.path, &contents)
+    }
+
+    /// Remembers `text` as sent in `channel`, moving it to the end if it
+    /// was already there
+    pub fn push(&mut self, channel: ChannelId, text: &str) -> io::Result<()> {
+        if text.trim().is_empty() {
+            return Ok(());
+        }
+
+        self.entries
+            .retain(|(id, entry)| !(*id == channel && entry == text));
+        self.entries.push((channel, text.to_string()));
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+
+        self.save()
+    }
+
+    /// The inputs sent in `channel`, newest first
+    pub fn channel(&self, channel: ChannelId) -> Vec<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|(id, _)| *id == channel)
+            .map(|(_, text)| text.as_str())
+            .collect()
+    }
+
+    /// The newest entry older than `before` containing `query`, in any
+    /// channel; `before` is an index as returned by this, or `None` to
+    /// search from the newest entry
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<usize> {
+        let before = before.unwrap_or(self.entries.len());
+        self.entries[..before]
+            .iter()
+            .rposition(|(_, text)| text.contains(query))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(|(_, text)| text.as_str())
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+};
+
+use image::{imageops::FilterType, GenericImageView, RgbaImage};
+use termion::event::Key;
+use tokio::sync::mpsc::UnboundedSender;
+use tui::{
+    style::{Color, Style},
+    text::{Span, Spans},
+};
+
+use crate::util::event::Event;
+
+/// Images are scaled down to fit in this many pixels in both directions
+/// as soon as they are decoded, so that the cache stays small. Half
+/// blocks never need more.
+const MAX_DECODED_SIZE: u32 = 512;
+
+/// Like `MAX_DECODED_SIZE`, when images are drawn with their pixels by
+/// the terminal
+const MAX_DECODED_SIZE_PIXELS: u32 = 2048;
+
+/// Decoded images are dropped past this many bytes, the least recently
+/// drawn first, and loaded again if they are drawn later
+const MAX_CACHED_BYTES: usize = 128 * 1024 * 1024;
+
+/// How colours are sent to the terminal
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+}
+
+impl ColorMode {
+    /// Guesses what the terminal supports from `$COLORTERM`.
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM").as_deref() {
+            Ok("truecolor") | Ok("24bit") => ColorMode::TrueColor,
+            _ => ColorMode::Ansi256,
+        }
+    }
+
+    fn color(self, [r, g, b, _]: [u8; 4]) -> Color {
+        match self {
+            ColorMode::TrueColor => Color::Rgb(r, g, b),
+            ColorMode::Ansi256 => {
+                // the 6x6x6 colour cube starts at 16
+                let level = |v: u8| (v as u16 * 5 + 127) / 255;
+                Color::Indexed((16 + 36 * level(r) + 6 * level(g) + level(b)) as u8)
+            }
+        }
+    }
+}
+
+enum ImageState {
+    Loading,
+    Loaded(RgbaImage),
+    Failed,
+    /// Dropped to keep the cache small
+    Evicted,
+}
+
+/// A half block preview, with the width and height it was made for
+type Preview = (u16, u16, Vec<Spans<'static>>);
+
+/// Decoded images by url, and their previews.
+///
+/// Rendering only has a shared reference, so what it changes is in cells.
+pub struct ImageCache {
+    pub enabled: bool,
+    color_mode: ColorMode,
+    /// How large images are kept, see `MAX_DECODED_SIZE`
+    decoded_size: u32,
+    images: HashMap<String, ImageState>,
+    /// When each loaded image was last drawn, counted in draws
+    last_used: RefCell<HashMap<String, u64>>,
+    draws: Cell<u64>,
+    /// Evicted images that were to be drawn, to be loaded again by
+    /// `request_wanted`
+    wanted: RefCell<HashSet<String>>,
+    /// The half block preview of each image, with the size in cells it
+    /// was made for
+    previews: RefCell<HashMap<String, Preview>>,
+}
+
+impl ImageCache {
+    /// `pixels` is whether the terminal draws the images itself, rather
+    /// than with half blocks.
+    pub fn new(enabled: bool, color_mode: ColorMode, pixels: bool) -> Self {
+        Self {
+            enabled,
+            color_mode,
+            decoded_size: if pixels {
+                MAX_DECODED_SIZE_PIXELS
+            } else {
+                MAX_DECODED_SIZE
+            },
+            images: HashMap::new(),
+            last_used: RefCell::new(HashMap::new()),
+            draws: Cell::new(0),
+            wanted: RefCell::new(HashSet::new()),
+            previews: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Starts loading the image at `url` in the background, unless it was
+    /// already requested. It is reported back through `tx` as an
+    /// `Event::ImageLoaded`.
+    pub fn request(&mut self, url: String, tx: UnboundedSender<Event<Key>>) {
+        if !self.enabled || !matches!(self.images.get(&url), None | Some(ImageState::Evicted)) {
+            return;
+        }
+
+        self.images.insert(url.clone(), ImageState::Loading);
+
+        let size = self.decoded_size;
+        tokio::spawn(async move {
+            let image = fetch(&url, size).await.map_err(|e| e.to_string());
+            let _ = tx.send(Event::ImageLoaded { url, image });
+        });
+    }
+
+    /// Loads again the evicted images that were to be drawn since the
+    /// last call
+    pub fn request_wanted(&mut self, tx: &UnboundedSender<Event<Key>>) {
+        let wanted = self.wanted.take();
+        for url in wanted {
+            self.request(url, tx.clone());
+        }
+    }
+
+    pub fn insert(&mut self, url: String, image: Result<RgbaImage, String>) {
+        let state = match image {
+            Ok(image) => ImageState::Loaded(image),
+            Err(_) => ImageState::Failed,
+        };
+        self.images.insert(url.clone(), state);
+        self.used(&url);
+        self.evict();
+    }
+
+    /// Drops the least recently drawn images until the cache fits in
+    /// `MAX_CACHED_BYTES`, keeping at least the latest one
+    fn evict(&mut self) {
+        let size = |state: &ImageState| match state {
+            ImageState::Loaded(image) => image.as_raw().len(),
+            _ => 0,
+        };
+        let mut total: usize = self.images.values().map(size).sum();
+
+        while total > MAX_CACHED_BYTES {
+            let last_used = self.last_used.get_mut();
+            let oldest = self
+                .images
+                .iter()
+                .filter(|(_, state)| matches!(state, ImageState::Loaded(_)))
+                .min_by_key(|(url, _)| last_used.get(*url).copied().unwrap_or(0))
+                .map(|(url, _)| url.clone());
+            let oldest = match oldest {
+                Some(oldest) if last_used.len() > 1 => oldest,
+                _ => break,
+            };
+
+            if let Some(state) = self.images.insert(oldest.clone(), ImageState::Evicted) {
+                total -= size(&state);
+            }
+            last_used.remove(&oldest);
+            self.previews.get_mut().remove(&oldest);
+        }
+    }
+
+    /// Notes that the image at `url` was drawn
+    fn used(&self, url: &str) {
+        let draws = self.draws.get() + 1;
+        self.draws.set(draws);
+        self.last_used.borrow_mut().insert(url.to_string(), draws);
+    }
+
+    /// The decoded image at `url`, if it is loaded
+    pub fn get(&self, url: &str) -> Option<&RgbaImage> {
+        match self.images.get(url) {
+            Some(ImageState::Loaded(image)) => {
+                self.used(url);
+                Some(image)
+            }
+            Some(ImageState::Evicted) => {
+                self.wanted.borrow_mut().insert(url.to_string());
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// The size in cells of the image at `url` drawn by the terminal, if
+    /// it is loaded: as large as it is, or smaller to fit in `max_width`
+    /// by `max_height` cells of `cell_size` pixels.
+    pub fn size_in_cells(
+        &self,
+        url: &str,
+        max_width: u16,
+        max_height: u16,
+        (cell_width, cell_height): (u32, u32),
+    ) -> Option<(u16, u16)> {
+        let (width, height) = self.get(url)?.dimensions();
+        if width == 0 || height == 0 || max_width == 0 || max_height == 0 {
+            return Some((0, 0));
+        }
+
+        let (cell_width, cell_height) = (cell_width.max(1) as f64, cell_height.max(1) as f64);
+        let scale = f64::min(
+            max_width as f64 * cell_width / width as f64,
+            max_height as f64 * cell_height / height as f64,
+        )
+        .min(1.0);
+
+        let cols = ((width as f64 * scale / cell_width).ceil() as u16).clamp(1, max_width);
+        let rows = ((height as f64 * scale / cell_height).ceil() as u16).clamp(1, max_height);
+        Some((cols, rows))
+    }
+
+    /// The preview of the image at `url` as lines of half blocks, fitting
+    /// in `max_width` by `max_height` cells, or a placeholder line if
+    /// the image is not loaded.
+    pub fn preview(&self, url: &str, max_width: u16, max_height: u16) -> Vec<Spans<'static>> {
+        let placeholder = |text: &'static str| {
+            vec![Spans::from(Span::styled(
+                text,
+                Style::default().fg(Color::DarkGray),
+            ))]
+        };
+
+        let image = match self.images.get(url) {
+            Some(ImageState::Failed) => return placeholder("  [image failed to load]"),
+            Some(ImageState::Loaded(_)) | Some(ImageState::Evicted) => match self.get(url) {
+                Some(image) => image,
+                None => return placeholder("  [loading image…]"),
+            },
+            Some(ImageState::Loading) | None => return placeholder("  [loading image…]"),
+        };
+
+        if let Some((width, height, preview)) = self.previews.borrow().get(url) {
+            if (*width, *height) == (max_width, max_height) {
+                return preview.clone();
+            }
+        }
+
+        // made again, for the new size, when the pane is resized
+        let preview = render_half_blocks(image, max_width, max_height, self.color_mode);
+        self.previews
+            .borrow_mut()
+            .insert(url.to_string(), (max_width, max_height, preview.clone()));
+        preview
+    }
+}
+
+async fn fetch(
+    url: &str,
+    size: u32,
+) -> Result<RgbaImage, Box<dyn std::error::Error + Send + Sync>> {
+    let bytes = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+
+    let image = tokio::task::spawn_blocking(move || {
+        image::load_from_memory(&bytes).map(|image| {
+            if image.width() > size || image.height() > size {
+                image.thumbnail(size, size).to_rgba8()
+            } else {
+                image.to_rgba8()
+            }
+        })
+    })
+    .await??;
+
+    Ok(image)
+}
+
+/// The size in cells an image of `width` by `height` pixels is drawn at
+/// with half blocks so that it fits in `max_width` by `max_height`
+/// cells. A cell holds one pixel horizontally and two vertically.
+fn fit_cells(width: u32, height: u32, max_width: u16, max_height: u16) -> (u16, u16) {
+    if width == 0 || height == 0 || max_width == 0 || max_height == 0 {
+        return (0, 0);
+    }
+
+    let scale = f64::min(
+        max_width as f64 / width as f64,
+        (max_height as f64 * 2.0) / height as f64,
+    )
+    .min(1.0);
+
+    let cols = ((width as f64 * scale).round() as u16).max(1);
+    let rows = (((height as f64 * scale) / 2.0).ceil() as u16).max(1);
+    (cols, rows)
+}
+
+fn render_half_blocks(
+    image: &RgbaImage,
+    max_width: u16,
+    max_height: u16,
+    color_mode: ColorMode,
+) -> Vec<Spans<'static>> {
+    let (width, height) = image.dimensions();
+    let (cols, rows) = fit_cells(width, height, max_width, max_height);
+    if cols == 0 || rows == 0 {
+        return Vec::new();
+    }
+
+    let resized =
+        image::imageops::resize(image, cols as u32, rows as u32 * 2, FilterType::Triangle);
+
+    (0..rows as u32)
+        .map(|row| {
+            let mut spans = vec![Span::raw("  ")];
+            spans.extend((0..cols as u32).map(|col| {
+                // the upper pixel is the foreground of `▀`, the lower one its background
+                let top = resized.get_pixel(col, row * 2).0;
+                let bottom = resized.get_pixel(col, row * 2 + 1).0;
+                Span::styled(
+                    "▀",
+                    Style::default()
+                        .fg(color_mode.color(top))
+                        .bg(color_mode.color(bottom)),
+                )
+            }));
+            Spans::from(spans)
+        })
+        .collect()
+}
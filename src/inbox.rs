@@ -0,0 +1,47 @@
+use robespierre::{model::user_opt_member::UserOptMember, robespierre_models::channels::Message};
+
+/// Older mentions are forgotten past this many
+const MAX_MENTIONS: usize = 100;
+
+/// A message mentioning or replying to us
+pub struct Mention {
+    pub message: Message,
+    pub author: UserOptMember,
+    /// Where it was sent, e.g. `Revolt #general`
+    pub place: String,
+}
+
+/// The mentions received over the socket in every channel, newest first
+#[derive(Default)]
+pub struct Inbox {
+    mentions: Vec<Mention>,
+}
+
+impl Inbox {
+    pub fn push(&mut self, mention: Mention) {
+        self.mentions.insert(0, mention);
+        self.mentions.truncate(MAX_MENTIONS);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.mentions.len() {
+            self.mentions.remove(index);
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Mention> {
+        self.mentions.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.mentions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mentions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Mention> {
+        self.mentions.iter()
+    }
+}
@@ -0,0 +1,110 @@
+use robespierre::robespierre_models::{channels::Channel, servers::Server};
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+use crate::{markdown, util};
+
+fn blank() -> Spans<'static> {
+    Spans::from(Span::raw(""))
+}
+
+/// The lines of the server info popup. `member_count` is `None` while the
+/// members are being fetched.
+pub fn server_lines(
+    server: &Server,
+    channels: &[Channel],
+    owner: &str,
+    member_count: Option<usize>,
+) -> Vec<Spans<'static>> {
+    let mut lines = vec![Spans::from(Span::styled(
+        server.name.clone(),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+
+    if let Some(description) = &server.description {
+        lines.push(blank());
+        lines.extend(markdown::render(description));
+    }
+
+    lines.push(blank());
+    lines.push(Spans::from(vec![
+        Span::styled("Owner: ", Style::default().fg(Color::Gray)),
+        Span::raw(owner.to_string()),
+    ]));
+    lines.push(Spans::from(vec![
+        Span::styled("Members: ", Style::default().fg(Color::Gray)),
+        Span::raw(member_count.map_or_else(|| "…".to_string(), |count| count.to_string())),
+    ]));
+
+    let channel_name = |id| {
+        channels
+            .iter()
+            .find(|channel| channel.id() == id)
+            .and_then(|channel| channel.name().map(|name| format!("#{}", name)))
+    };
+
+    if let Some(categories) = server.categories.as_ref().filter(|it| !it.is_empty()) {
+        lines.push(blank());
+        lines.push(util::heading("Categories"));
+        for category in categories {
+            lines.push(Spans::from(Span::styled(
+                category.title.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for name in category.channels.iter().filter_map(|id| channel_name(*id)) {
+                lines.push(Spans::from(Span::raw(format!("  {}", name))));
+            }
+        }
+    }
+
+    let mut roles: Vec<_> = server
+        .roles
+        .iter()
+        .flatten()
+        .map(|(_, role)| role)
+        .collect();
+    if !roles.is_empty() {
+        roles.sort_by_key(|role| role.rank);
+
+        lines.push(blank());
+        lines.push(util::heading("Roles"));
+        for role in roles {
+            let color = role
+                .colour
+                .as_deref()
+                .and_then(util::hex_color)
+                .unwrap_or(Color::Gray);
+            lines.push(Spans::from(vec![
+                Span::styled("● ", Style::default().fg(color)),
+                Span::raw(role.name.clone()),
+            ]));
+        }
+    }
+
+    lines
+}
+
+/// The lines of the channel info popup
+pub fn channel_lines(channel: &Channel) -> Vec<Spans<'static>> {
+    let mut lines = vec![Spans::from(Span::styled(
+        channel
+            .name()
+            .map_or_else(String::new, |name| format!("#{}", name)),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+
+    lines.push(blank());
+    match channel.description() {
+        Some(description) if !description.trim().is_empty() => {
+            lines.extend(markdown::render(description));
+        }
+        _ => lines.push(Spans::from(Span::styled(
+            "This channel has no topic",
+            Style::default().fg(Color::DarkGray),
+        ))),
+    }
+
+    lines
+}
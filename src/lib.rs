@@ -1,32 +1,57 @@
 use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
     convert::{TryFrom, TryInto},
-    io::Stdout,
+    io::{self, Stdout},
+    path::PathBuf,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
+use graphics::{GraphicsProtocol, Placement};
 use robespierre::{
-    model::{user_opt_member::UserOptMember, ChannelIdExt, MessageExt, ServerIdExt},
+    model::{user_opt_member::UserOptMember, ChannelIdExt, MessageExt, ServerIdExt, UserIdExt},
     robespierre_cache::{Cache, HasCache},
     robespierre_http::{HasHttp, Http},
     robespierre_models::{
-        channels::{Channel, Message},
-        events::ServerToClientEvent,
-        id::ChannelId,
+        autumn::Attachment,
+        channels::{Channel, ChannelPermissions, Message, MessageContent, ReplyData},
+        events::{ClientToServerEvent, ServerToClientEvent},
+        id::{ChannelId, MessageId, ServerId, UserId},
         servers::Server,
+        users::{Presence, RelationshipStatus, UserEditPatch, UserField, UserStatus},
     },
 };
 use termion::{event::Key, input::MouseTerminal, raw::RawTerminal, screen::AlternateScreen};
 use tui::{
     backend::TermionBackend,
-    layout::{Constraint, Corner, Direction, Layout},
-    style::{Color, Style},
+    layout::{Constraint, Corner, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
-    Frame,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
 };
 use unicode_width::UnicodeWidthStr;
-use util::event::{Event, Events};
+use util::event::{Event, Events, TransferStatus};
 
+mod commands;
+mod completion;
+mod direct;
+mod drafts;
+mod editor;
+mod embeds;
+mod emoji;
+mod graphics;
+mod history;
+mod images;
+mod inbox;
+mod info;
+mod markdown;
+mod members;
+mod presence;
+mod profile;
+mod transfers;
+mod unreads;
 #[allow(dead_code)]
 pub mod util;
 
@@ -35,23 +60,204 @@ type B = TermionBackend<AlternateScreen<MouseTerminal<RawTerminal<Stdout>>>>;
 enum InputMode {
     Normal,
     Editing,
+    /// Editing an already sent message; `draft` is what was in the
+    /// input box before, and is restored if the edit is cancelled.
+    EditingMessage {
+        message: MessageId,
+        draft: String,
+    },
+    /// Moving a cursor over the message list; `selected` is an index
+    /// into `messages`.
+    Selecting {
+        selected: usize,
+    },
+    /// Searching the input history backwards for `query`, with the match
+    /// in the input box; `found` is its index in the history, and `draft`
+    /// is what was in the input box before.
+    SearchingHistory {
+        query: String,
+        found: Option<usize>,
+        draft: String,
+    },
+}
+
+enum Popup {
+    /// Asks for confirmation before deleting the message at `index`
+    /// in `messages`.
+    ConfirmDelete {
+        index: usize,
+    },
+    /// The actions that can be done on the message at `index` in
+    /// `messages`; `selected` is an index into `MessageAction::ALL`.
+    MessageActions {
+        index: usize,
+        selected: usize,
+    },
+    /// The attachments of the message at `index` in `messages`;
+    /// `selected` is an index into its attachments.
+    Attachments {
+        index: usize,
+        selected: usize,
+    },
+    /// Picks a file to attach to the next message
+    FilePicker {
+        dir: PathBuf,
+        entries: Vec<transfers::FileEntry>,
+        selected: usize,
+    },
+    Profile {
+        user: Box<UserOptMember>,
+    },
+    /// Asks for confirmation before blocking `user`, going back to their
+    /// profile after
+    ConfirmBlock {
+        user: Box<UserOptMember>,
+    },
+    /// The messages mentioning us; `selected` is an index into the inbox
+    Inbox {
+        selected: usize,
+    },
+    /// Our direct message conversations; `selected` is an index into
+    /// them
+    DirectMessages {
+        selected: usize,
+    },
+    /// Everything about the current server, scrolled down `scroll` lines,
+    /// which `render` keeps within the text; `owner` is the name of its
+    /// owner
+    ServerInfo {
+        owner: String,
+        scroll: Cell<u16>,
+    },
+    /// The full topic of the current channel, scrolled down `scroll` lines
+    ChannelInfo {
+        scroll: Cell<u16>,
+    },
+    /// Sets our presence, `selected` being an index into
+    /// `presence::CHOICES`, and custom status text
+    SetStatus {
+        selected: usize,
+        text: String,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum MessageAction {
+    Reply,
+    Edit,
+    Delete,
+    CopyText,
+    CopyLink,
+    Attachments,
+    ViewAuthor,
+    JumpToReferenced,
+    JoinInvite,
+}
+
+impl MessageAction {
+    const ALL: [MessageAction; 9] = [
+        MessageAction::Reply,
+        MessageAction::Edit,
+        MessageAction::Delete,
+        MessageAction::CopyText,
+        MessageAction::CopyLink,
+        MessageAction::Attachments,
+        MessageAction::ViewAuthor,
+        MessageAction::JumpToReferenced,
+        MessageAction::JoinInvite,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            MessageAction::Reply => "Reply",
+            MessageAction::Edit => "Edit",
+            MessageAction::Delete => "Delete",
+            MessageAction::CopyText => "Copy text",
+            MessageAction::CopyLink => "Copy link",
+            MessageAction::Attachments => "Attachments",
+            MessageAction::ViewAuthor => "View author profile",
+            MessageAction::JumpToReferenced => "Jump to referenced message",
+            MessageAction::JoinInvite => "Join invited server",
+        }
+    }
 }
 
+/// The message the next sent message will be a reply to
+struct Reply {
+    message: MessageId,
+    author_name: String,
+    /// Whether the author of `message` should be pinged by the reply
+    mention: bool,
+}
+
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+/// Someone who started typing is forgotten after this long without
+/// hearing from them again
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+/// While typing, `BeginTyping` is sent again after this long
+const TYPING_RESEND_INTERVAL: Duration = Duration::from_secs(3);
+
+/// What `/shrug` sends, escaped so the underscores are not taken for
+/// italics
+const SHRUG: &str = r"¯\\\_(ツ)\_/¯";
+
+/// How many completion candidates are shown at once
+const MAX_COMPLETION_ROWS: usize = 8;
+
+/// How many picked completions are remembered to rank them first
+const MAX_RECENT_COMPLETIONS: usize = 20;
+
+/// The input box grows up to this many lines, then scrolls
+const MAX_INPUT_LINES: usize = 8;
+
+/// How many of the latest messages are loaded when opening a channel
+const HISTORY_LENGTH: usize = 50;
+
 enum AppStateInternal {
     ServerChannel {
         /// Current value of the input box
-        input: String,
+        input: editor::Editor,
+        /// How far back in the history of the channel the input box was
+        /// recalled from, 0 being the latest, and what it had before
+        recalled: Option<(usize, String)>,
+        /// Shown while completing the word before the cursor
+        completion: Option<completion::Completion>,
         /// Current input mode
         input_mode: InputMode,
 
         /// History of recorded messages
         messages: Vec<(Message, UserOptMember)>,
+        /// Messages that are replied to, but are not in `messages`
+        referenced_messages: HashMap<MessageId, (Message, UserOptMember)>,
+        /// Invites linked to in messages, by invite code
+        invites: HashMap<String, embeds::InviteState>,
+        /// The first message that was unread when the channel was opened,
+        /// where the "NEW" divider is drawn
+        first_unread: Option<MessageId>,
+        /// Who is typing in `current_channel`, and when they were last
+        /// heard of
+        typing: Vec<(UserId, String, Instant)>,
+        /// When `BeginTyping` was last sent, if we are typing
+        typing_sent: Option<Instant>,
+        /// Set while composing a reply
+        replying_to: Option<Reply>,
+        /// File to upload and attach to the next sent message
+        attaching: Option<PathBuf>,
 
         server: Server,
 
         server_channels: Vec<Channel>,
 
         current_channel: Channel,
+
+        /// Popup drawn over everything else, which takes all the input
+        popup: Option<Popup>,
+        /// Message shown under the input box, cleared on the next key press
+        status: Option<Status>,
     },
 }
 
@@ -59,8 +265,69 @@ enum AppStateInternal {
 pub struct AppState {
     state: AppStateInternal,
     server_list: Option<Vec<Server>>,
+    /// The id of the logged in user, known after the `Ready` event
+    me: Option<UserId>,
+    /// Our presence and custom status text
+    presence: Presence,
+    status_text: Option<String>,
+    /// When the last key was pressed
+    last_input: Instant,
+    /// Whether `presence` was set to Idle by us rather than the user
+    auto_idle: bool,
 
+    config: AppConfig,
     ctx: AppCtx,
+
+    images: images::ImageCache,
+    graphics: graphics::Graphics,
+    /// The images `render` left room for, to be drawn by `draw_graphics`
+    placements: RefCell<Vec<Placement>>,
+
+    unreads: unreads::Unreads,
+    inbox: inbox::Inbox,
+    direct_messages: direct::DirectMessages,
+
+    /// The member lists of the servers they were fetched for, `None`
+    /// while being fetched
+    members: HashMap<ServerId, Option<members::MemberList>>,
+    /// Whether the member list pane is drawn
+    show_members: bool,
+    /// The profiles opened so far, `None` while being fetched
+    profiles: HashMap<UserId, Option<profile::Profile>>,
+    /// What was sent from the input box
+    history: history::History,
+    drafts: drafts::Drafts,
+    /// What completions replaced words with, most recent first
+    recent_completions: Vec<String>,
+    /// The channel being loaded to switch to, the last one asked for
+    opening: Option<ChannelId>,
+}
+
+pub struct AppConfig {
+    /// Where attachments are downloaded to
+    pub download_dir: PathBuf,
+    /// Whether image attachments and avatars are previewed
+    pub image_previews: bool,
+    /// We become Idle after this long without a key press, unless we
+    /// chose another presence than Online
+    pub idle_after: Option<Duration>,
+    /// Where what is kept between sessions is stored
+    pub data_dir: PathBuf,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        let home = std::env::var_os("HOME").map_or_else(PathBuf::new, PathBuf::from);
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map_or_else(|| home.join(".local/share"), PathBuf::from);
+
+        Self {
+            download_dir: home.join("Downloads"),
+            image_previews: true,
+            idle_after: Some(Duration::from_secs(10 * 60)),
+            data_dir: data_home.join("tui-revolt"),
+        }
+    }
 }
 
 struct AppCtx {
@@ -88,45 +355,600 @@ impl AppState {
     pub async fn new(
         cache: Arc<Cache>,
         http: Arc<Http>,
+        config: AppConfig,
         open_at: OpenAt,
-    ) -> robespierre::Result<Self> {
+    ) -> Result<Self, String> {
         let ctx = AppCtx { cache, http };
-        let state = match open_at {
+        // fetched once connected
+        let unreads = unreads::Unreads::default();
+        let mut state = match open_at {
             OpenAt::Channel(channel_id) => {
-                let current_channel = channel_id.channel(&ctx).await?;
-
-                let server_id = current_channel.server_id();
-
-                match server_id {
-                    Some(server_id) => {
-                        let server = server_id.server(&ctx).await?;
-                        let mut server_channels = Vec::with_capacity(server.channels.len());
-
-                        for ch in server.channels.iter() {
-                            server_channels.push(ch.channel(&ctx).await?);
-                        }
-
-                        AppStateInternal::ServerChannel {
-                            input: String::new(),
-                            input_mode: InputMode::Normal,
-                            messages: Vec::new(),
-                            current_channel,
-                            server,
-                            server_channels,
-                        }
-                    }
-                    None => {
-                        todo!()
-                    }
-                }
+                AppStateInternal::new(load_channel(&ctx, channel_id).await?, &unreads)
             }
         };
 
+        let drafts = drafts::Drafts::load(config.data_dir.join("drafts"));
+        let protocol = GraphicsProtocol::detect();
+        let AppStateInternal::ServerChannel {
+            input,
+            current_channel,
+            ..
+        } = &mut state;
+        if let Some(draft) = drafts.get(current_channel.id()) {
+            input.set(draft.to_string());
+        }
+
         Ok(Self {
             state,
             ctx,
             server_list: None,
+            me: None,
+            presence: Presence::Online,
+            status_text: None,
+            last_input: Instant::now(),
+            auto_idle: false,
+            images: images::ImageCache::new(
+                config.image_previews,
+                images::ColorMode::detect(),
+                protocol != GraphicsProtocol::Blocks,
+            ),
+            graphics: graphics::Graphics::new(protocol),
+            placements: RefCell::new(Vec::new()),
+            unreads,
+            inbox: inbox::Inbox::default(),
+            direct_messages: direct::DirectMessages::default(),
+            members: HashMap::new(),
+            show_members: true,
+            profiles: HashMap::new(),
+            history: history::History::load(config.data_dir.join("history")),
+            drafts,
+            recent_completions: Vec::new(),
+            opening: None,
+            config,
+        })
+    }
+}
+
+/// A channel with what is needed to show it, loaded in the background
+/// before switching to it
+pub struct LoadedChannel {
+    channel: Channel,
+    server: Server,
+    server_channels: Vec<Channel>,
+    messages: Vec<(Message, UserOptMember)>,
+    referenced_messages: HashMap<MessageId, (Message, UserOptMember)>,
+    invites: HashMap<String, embeds::InviteState>,
+}
+
+/// Fetches `channel_id` with its latest messages and what they refer to.
+/// Only server channels can be shown, the error for others tells so.
+async fn load_channel(ctx: &AppCtx, channel_id: ChannelId) -> Result<LoadedChannel, String> {
+    let channel = channel_id.channel(ctx).await.map_err(|e| e.to_string())?;
+
+    let server_id = match channel.server_id() {
+        Some(server_id) => server_id,
+        None => return Err("direct messages and groups cannot be shown yet".to_string()),
+    };
+
+    let fetched = async {
+        let server = server_id.server(ctx).await?;
+        let mut server_channels = Vec::with_capacity(server.channels.len());
+
+        for ch in server.channels.iter() {
+            server_channels.push(ch.channel(ctx).await?);
+        }
+
+        let mut history = ctx.http.fetch_messages(channel_id, HISTORY_LENGTH).await?;
+        history.sort_by_key(|message| message.id);
+
+        let mut messages = Vec::with_capacity(history.len());
+        for message in history {
+            let author = message.author_user_opt_member(ctx).await?;
+            messages.push((message, author));
+        }
+
+        Ok::<_, robespierre::Error>((server, server_channels, messages))
+    };
+    let (server, server_channels, messages) = fetched.await.map_err(|e| e.to_string())?;
+
+    let mut referenced_messages = HashMap::new();
+    let mut invites = HashMap::new();
+    for (message, _) in messages.iter() {
+        load_references(
+            ctx,
+            message,
+            &messages,
+            &mut referenced_messages,
+            &mut invites,
+        )
+        .await;
+    }
+
+    Ok(LoadedChannel {
+        channel,
+        server,
+        server_channels,
+        messages,
+        referenced_messages,
+        invites,
+    })
+}
+
+/// Like `load_channel`, in the background, sending the result as
+/// `Event::ChannelLoaded`
+fn spawn_load_channel(
+    ctx: &AppCtx,
+    events: &Events,
+    channel: ChannelId,
+    select: Option<MessageId>,
+) {
+    let ctx = AppCtx {
+        cache: Arc::clone(&ctx.cache),
+        http: Arc::clone(&ctx.http),
+    };
+    let tx = events.sender();
+    tokio::spawn(async move {
+        let loaded = load_channel(&ctx, channel).await;
+        let _ = tx.send(Event::ChannelLoaded {
+            id: channel,
+            select,
+            channel: loaded.map(Box::new),
+        });
+    });
+}
+
+impl AppStateInternal {
+    /// Shows a loaded channel, `unreads` telling what was read in it.
+    fn new(loaded: LoadedChannel, unreads: &unreads::Unreads) -> Self {
+        let LoadedChannel {
+            channel,
+            server,
+            server_channels,
+            messages,
+            referenced_messages,
+            invites,
+        } = loaded;
+
+        AppStateInternal::ServerChannel {
+            input: editor::Editor::default(),
+            recalled: None,
+            completion: None,
+            input_mode: InputMode::Normal,
+            first_unread: find_first_unread(&messages, channel.id(), unreads),
+            messages,
+            referenced_messages,
+            invites,
+            typing: Vec::new(),
+            typing_sent: None,
+            replying_to: None,
+            attaching: None,
+            current_channel: channel,
+            server,
+            server_channels,
+            popup: None,
+            status: None,
+        }
+    }
+}
+
+/// What was typed in the input box and not sent, leaving aside a message
+/// being edited or an entry of the history being looked at
+fn unsent_text<'a>(
+    input: &'a editor::Editor,
+    input_mode: &'a InputMode,
+    recalled: &'a Option<(usize, String)>,
+) -> &'a str {
+    match (input_mode, recalled) {
+        (InputMode::EditingMessage { draft, .. }, _)
+        | (InputMode::SearchingHistory { draft, .. }, _)
+        | (_, Some((_, draft))) => draft,
+        _ => input,
+    }
+}
+
+/// The completions of the word before the cursor, if it is one that is
+/// completed: `@member`, `#channel`, `:emoji` or a `/command` starting the
+/// input
+fn complete(
+    input: &editor::Editor,
+    members: Option<&members::MemberList>,
+    messages: &[(Message, UserOptMember)],
+    server_channels: &[Channel],
+    recent: &[String],
+) -> Option<completion::Completion> {
+    use completion::Candidate;
+
+    let (start, word) = input.word_before_cursor();
+    let mut chars = word.chars();
+    let sigil = chars.next()?;
+    let query = chars.as_str();
+    let mut recent = recent.to_vec();
+
+    let candidates = match sigil {
+        '@' => {
+            let mut users: Vec<(UserId, String)> = members
+                .into_iter()
+                .flat_map(|members| members.names())
+                .map(|(user, name)| (user, name.to_string()))
+                .collect();
+            // after what was picked, whoever talked last comes first
+            for (message, author) in messages.iter().rev() {
+                let mention = format!("<@{}> ", message.author);
+                if !recent.contains(&mention) {
+                    recent.push(mention);
+                }
+                if !users.iter().any(|(user, _)| *user == message.author) {
+                    users.push((message.author, author.display_name().to_string()));
+                }
+            }
+
+            users
+                .into_iter()
+                .map(|(user, name)| Candidate {
+                    label: format!("@{}", name),
+                    replacement: format!("<@{}> ", user),
+                    name,
+                })
+                .collect()
+        }
+        '#' => server_channels
+            .iter()
+            .filter_map(|channel| {
+                let name = channel.name()?.clone();
+                Some(Candidate {
+                    label: format!("#{}", name),
+                    replacement: format!("<#{}> ", channel.id()),
+                    name,
+                })
+            })
+            .collect(),
+        ':' => emoji::SHORTCODES
+            .iter()
+            .map(|(code, emoji)| Candidate {
+                label: format!("{} :{}:", emoji, code),
+                replacement: emoji.to_string(),
+                name: code.to_string(),
+            })
+            .collect(),
+        '/' if start == 0 => commands::COMMANDS
+            .iter()
+            .map(|command| Candidate {
+                label: command.usage(),
+                replacement: format!("/{} ", command.name),
+                name: command.name.to_string(),
+            })
+            .collect(),
+        _ => return None,
+    };
+
+    let candidates = completion::rank(query, candidates, &recent);
+    if candidates.is_empty() {
+        return None;
+    }
+
+    Some(completion::Completion {
+        start,
+        word: word.to_string(),
+        candidates,
+        selected: 0,
+    })
+}
+
+/// The id in a `<@user>` or `<#channel>` token, `prefix` being its start
+fn mentioned_id<T: std::str::FromStr>(token: &str, prefix: &str) -> Option<T> {
+    token.strip_prefix(prefix)?.strip_suffix('>')?.parse().ok()
+}
+
+/// The user `name` stands for: a `<@user>` mention, or the name of a
+/// member or of someone who wrote in the channel
+fn find_user(
+    name: &str,
+    members: Option<&members::MemberList>,
+    messages: &[(Message, UserOptMember)],
+) -> Option<UserId> {
+    if let Some(user) = mentioned_id(name, "<@") {
+        return Some(user);
+    }
+
+    let name = name.trim_start_matches('@');
+    members
+        .into_iter()
+        .flat_map(|members| members.names())
+        .find(|(_, member)| member.eq_ignore_ascii_case(name))
+        .map(|(user, _)| user)
+        .or_else(|| {
+            messages
+                .iter()
+                .find(|(_, author)| {
+                    author.display_name().eq_ignore_ascii_case(name)
+                        || author.user.username.eq_ignore_ascii_case(name)
+                })
+                .map(|(message, _)| message.author)
+        })
+}
+
+/// Sends `content` to `channel`, as a reply if `reply` is set
+async fn send_message(
+    ctx: &AppCtx,
+    channel: ChannelId,
+    content: String,
+    reply: Option<Reply>,
+) -> robespierre::Result {
+    channel
+        .send_message(ctx, |m| match reply {
+            Some(reply) => m.content(content).reply(ReplyData {
+                id: reply.message,
+                mention: reply.mention,
+            }),
+            None => m.content(content),
         })
+        .await
+        .map(|_| ())
+}
+
+/// Replaces the completed word with the selected candidate
+fn accept_completion(
+    input: &mut editor::Editor,
+    completion: completion::Completion,
+    recent: &mut Vec<String>,
+) {
+    let replacement = &completion.candidates[completion.selected].replacement;
+    input.replace_before_cursor(completion.start, replacement);
+
+    recent.retain(|picked| picked != replacement);
+    recent.insert(0, replacement.clone());
+    recent.truncate(MAX_RECENT_COMPLETIONS);
+}
+
+/// The first message of `messages` after what was last read in `channel`,
+/// none until that is known
+fn find_first_unread(
+    messages: &[(Message, UserOptMember)],
+    channel: ChannelId,
+    unreads: &unreads::Unreads,
+) -> Option<MessageId> {
+    if !unreads.is_loaded() {
+        return None;
+    }
+
+    let last_read = unreads.last_read(channel);
+    messages
+        .iter()
+        .map(|(message, _)| message.id)
+        .find(|id| last_read.map_or(true, |last_read| *id > last_read))
+}
+
+/// Adds `message` to the inbox if it mentions us or replies to one of
+/// our messages. What it replies to is fetched in the background if it is
+/// not at hand, and the mention is sent as `Event::Mentioned`.
+fn spawn_check_mention(
+    ctx: &AppCtx,
+    events: &Events,
+    message: &Message,
+    me: UserId,
+    messages: &[(Message, UserOptMember)],
+    referenced_messages: &HashMap<MessageId, (Message, UserOptMember)>,
+) {
+    let mut mentioned = message.mentions.iter().flatten().any(|user| *user == me);
+    let mut unknown = Vec::new();
+    for id in message.replies.iter().flatten() {
+        let known = messages
+            .iter()
+            .find(|(m, _)| m.id == *id)
+            .or_else(|| referenced_messages.get(id));
+
+        match known {
+            Some((referenced, _)) => mentioned |= referenced.author == me,
+            None => unknown.push(*id),
+        }
+    }
+
+    if !mentioned && unknown.is_empty() {
+        return;
+    }
+
+    let ctx = AppCtx {
+        cache: Arc::clone(&ctx.cache),
+        http: Arc::clone(&ctx.http),
+    };
+    let tx = events.sender();
+    let message = message.clone();
+    tokio::spawn(async move {
+        if !mentioned && !replies_to(&ctx, message.channel, &unknown, me).await {
+            return;
+        }
+
+        if let Ok(author) = message.author_user_opt_member(&ctx).await {
+            let place = channel_place(&ctx, message.channel).await;
+            let _ = tx.send(Event::Mentioned(Box::new(inbox::Mention {
+                message,
+                author,
+                place,
+            })));
+        }
+    });
+}
+
+/// Whether one of the messages `ids` of `channel` was sent by `user`
+async fn replies_to(ctx: &AppCtx, channel: ChannelId, ids: &[MessageId], user: UserId) -> bool {
+    for id in ids {
+        if let Ok(referenced) = ctx.http.fetch_message(channel, *id).await {
+            if referenced.author == user {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+/// Where messages in `channel` are sent, e.g. `Revolt #general`
+async fn channel_place(ctx: &AppCtx, channel: ChannelId) -> String {
+    let channel = match channel.channel(ctx).await {
+        Ok(channel) => channel,
+        Err(_) => return "Unknown channel".to_string(),
+    };
+
+    let name = channel.name().map_or_else(
+        || "Direct messages".to_string(),
+        |name| format!("#{}", name),
+    );
+
+    match channel.server_id() {
+        Some(server) => match server.server(ctx).await {
+            Ok(server) => format!("{} {}", server.name, name),
+            Err(_) => name,
+        },
+        None => name,
+    }
+}
+
+/// Sets our presence and custom status text, removing the text if `None`
+async fn set_status(ctx: &AppCtx, presence: Presence, text: Option<String>) -> robespierre::Result {
+    let remove = if text.is_none() {
+        Some(UserField::StatusText)
+    } else {
+        None
+    };
+
+    let patch = UserEditPatch {
+        status: Some(UserStatus {
+            text,
+            presence: Some(presence),
+        }),
+        remove,
+        ..Default::default()
+    };
+
+    ctx.http.edit_user(patch).await
+}
+
+/// Records that the user did something, bringing them back Online if
+/// they were made Idle for doing nothing
+fn note_activity(
+    ctx: &AppCtx,
+    last_input: &mut Instant,
+    auto_idle: &mut bool,
+    presence: &mut Presence,
+    status_text: &Option<String>,
+) {
+    *last_input = Instant::now();
+    if *auto_idle {
+        *auto_idle = false;
+        *presence = Presence::Online;
+        spawn_set_status(ctx, Presence::Online, status_text.clone());
+    }
+}
+
+/// Like `set_status`, in the background, for changes the user did not
+/// explicitly ask for
+fn spawn_set_status(ctx: &AppCtx, presence: Presence, text: Option<String>) {
+    let ctx = AppCtx {
+        cache: Arc::clone(&ctx.cache),
+        http: Arc::clone(&ctx.http),
+    };
+    tokio::spawn(async move {
+        let _ = set_status(&ctx, presence, text).await;
+    });
+}
+
+/// The popup showing the profile of `user`, fetching what is not known
+/// about them yet
+fn profile_popup(
+    ctx: &AppCtx,
+    images: &mut images::ImageCache,
+    events: &Events,
+    profiles: &mut HashMap<UserId, Option<profile::Profile>>,
+    user: UserOptMember,
+) -> Popup {
+    if let Some(avatar) = &user.user.avatar {
+        images.request(avatar_url(avatar), events.sender());
+    }
+
+    let id = user.user.id;
+    if !profiles.contains_key(&id) {
+        profiles.insert(id, None);
+
+        let http = Arc::clone(&ctx.http);
+        let tx = events.sender();
+        tokio::spawn(async move {
+            let profile = async {
+                let profile = http.fetch_user_profile(id).await?;
+                let mutual = http.fetch_mutual_friends_and_servers(id).await?;
+                Ok::<_, robespierre::Error>((profile.content, mutual.servers))
+            };
+            let _ = tx.send(Event::ProfileLoaded {
+                user: id,
+                profile: profile.await.map_err(|e| e.to_string()),
+            });
+        });
+    }
+
+    Popup::Profile {
+        user: Box::new(user),
+    }
+}
+
+/// Fetches what `message` refers to and needs to be drawn: the messages
+/// it replies to, its images and the invites it links to.
+async fn load_message_extras(
+    ctx: &AppCtx,
+    images: &mut images::ImageCache,
+    events: &Events,
+    message: &Message,
+    messages: &[(Message, UserOptMember)],
+    referenced_messages: &mut HashMap<MessageId, (Message, UserOptMember)>,
+    invites: &mut HashMap<String, embeds::InviteState>,
+) {
+    load_references(ctx, message, messages, referenced_messages, invites).await;
+    request_images(images, events, message);
+}
+
+/// Fetches the messages `message` replies to and the invites it links to
+async fn load_references(
+    ctx: &AppCtx,
+    message: &Message,
+    messages: &[(Message, UserOptMember)],
+    referenced_messages: &mut HashMap<MessageId, (Message, UserOptMember)>,
+    invites: &mut HashMap<String, embeds::InviteState>,
+) {
+    for id in message.replies.iter().flatten() {
+        if messages.iter().any(|(m, _)| m.id == *id) || referenced_messages.contains_key(id) {
+            continue;
+        }
+
+        if let Ok(referenced) = ctx.http.fetch_message(message.channel, *id).await {
+            if let Ok(author) = referenced.author_user_opt_member(ctx).await {
+                referenced_messages.insert(*id, (referenced, author));
+            }
+        }
+    }
+
+    for code in embeds::invite_codes(message_text(message)) {
+        if invites.contains_key(code) {
+            continue;
+        }
+
+        let invite = match ctx.http.fetch_invite(code).await {
+            Ok(invite) => embeds::InviteState::Loaded(invite),
+            Err(_) => embeds::InviteState::Invalid,
+        };
+        invites.insert(code.to_string(), invite);
+    }
+}
+
+/// Starts downloading the images of `message` to preview them
+fn request_images(images: &mut images::ImageCache, events: &Events, message: &Message) {
+    for attachment in message.attachments.iter().flatten() {
+        if is_image(attachment) {
+            images.request(attachment_url(attachment), events.sender());
+        }
+    }
+
+    for embed in message.embeds.iter().flatten() {
+        if let Some(url) = embeds::embed_image(embed) {
+            images.request(url.to_string(), events.sender());
+        }
     }
 }
 
@@ -154,6 +976,199 @@ where
     }
 }
 
+const APP_URL: &str = "https://app.revolt.chat";
+pub(crate) const AUTUMN_URL: &str = "https://autumn.revolt.chat";
+
+fn attachment_url(attachment: &Attachment) -> String {
+    format!("{}/attachments/{}", AUTUMN_URL, attachment.id)
+}
+
+fn avatar_url(avatar: &Attachment) -> String {
+    format!("{}/avatars/{}", AUTUMN_URL, avatar.id)
+}
+
+fn is_image(attachment: &Attachment) -> bool {
+    attachment.content_type.starts_with("image/")
+}
+
+/// A one line description of an attachment, e.g. `cat.png (1.5 MiB, image/png)`
+fn attachment_line(attachment: &Attachment) -> String {
+    format!(
+        "{} ({}, {})",
+        attachment.filename,
+        util::format_size(attachment.size as u64),
+        attachment.content_type
+    )
+}
+
+fn message_text(message: &Message) -> &str {
+    match &message.content {
+        MessageContent::Content(content) => content.as_str(),
+        MessageContent::SystemMessage(_) => "",
+    }
+}
+
+/// Whether `user` is allowed to delete other people's messages in `server`.
+///
+/// Only the server-wide permissions of `user`'s roles are taken into
+/// account, channel overrides are not.
+async fn can_manage_messages(ctx: &AppCtx, server: &Server, user: UserId) -> bool {
+    if server.owner == user {
+        return true;
+    }
+
+    let member = match ctx.http.fetch_member(server.id, user).await {
+        Ok(member) => member,
+        Err(_) => return false,
+    };
+
+    let mut permissions = server.default_permissions.1;
+    if let (Some(roles), Some(member_roles)) = (&server.roles, &member.roles) {
+        for role in member_roles.iter().filter_map(|id| roles.get(id)) {
+            permissions |= role.permissions.1;
+        }
+    }
+
+    permissions.contains(ChannelPermissions::MANAGE_MESSAGES)
+}
+
+/// Keeps the selection cursor inside `messages` after it shrinks, leaving
+/// selection mode if there is nothing left to select.
+fn clamp_selection(input_mode: &mut InputMode, len: usize) {
+    if let InputMode::Selecting { selected } = input_mode {
+        if len == 0 {
+            *input_mode = InputMode::Normal;
+        } else if *selected >= len {
+            *selected = len - 1;
+        }
+    }
+}
+
+/// A one line preview of a message that is replied to.
+fn reply_preview<'a>(
+    id: &MessageId,
+    messages: &'a [(Message, UserOptMember)],
+    referenced_messages: &'a HashMap<MessageId, (Message, UserOptMember)>,
+) -> Spans<'a> {
+    let style = Style::default().fg(Color::DarkGray);
+
+    let referenced = messages
+        .iter()
+        .find(|(m, _)| m.id == *id)
+        .or_else(|| referenced_messages.get(id));
+
+    match referenced {
+        Some((message, author)) => Spans::from(vec![
+            Span::styled("╭ ", style),
+            Span::styled(
+                author.display_name().to_string(),
+                style.add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(": ", style),
+            Span::styled(message_text(message).lines().next().unwrap_or(""), style),
+        ]),
+        None => Spans::from(Span::styled("╭ (unknown message)", style)),
+    }
+}
+
+/// Where an info popup scrolled to `scroll` lines down goes after `key`
+fn scroll_info(scroll: u16, key: Key) -> u16 {
+    match key {
+        Key::Up | Key::Char('k') => scroll.saturating_sub(1),
+        Key::Down | Key::Char('j') => scroll.saturating_add(1),
+        Key::PageUp => scroll.saturating_sub(10),
+        Key::PageDown => scroll.saturating_add(10),
+        Key::Home | Key::Char('g') => 0,
+        _ => scroll,
+    }
+}
+
+/// Keeps a popup drawn in `area` from scrolling past the end of `lines`
+fn clamp_scroll(scroll: &Cell<u16>, lines: &[Spans], area: Rect) {
+    // inside the borders
+    let height = util::wrapped_height(lines, area.width.saturating_sub(2));
+    let max = height.saturating_sub(area.height.saturating_sub(2));
+    scroll.set(scroll.get().min(max));
+}
+
+/// The red line drawn above the first unread message, `width` columns wide
+fn new_divider(width: u16) -> Spans<'static> {
+    let rule = "─".repeat((width as usize).saturating_sub(5) / 2);
+    Spans::from(Span::styled(
+        format!("{} NEW {}", rule, rule),
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    ))
+}
+
+/// The lines previewing the image at `url` in at most `max_width` by
+/// `max_height` cells.
+///
+/// When the image is drawn by `draw_graphics` instead of with half blocks,
+/// the lines are blank and the size of the area left for it is returned.
+fn image_preview(
+    app: &AppState,
+    url: &str,
+    max_width: u16,
+    max_height: u16,
+) -> (Vec<Spans<'static>>, Option<(u16, u16)>) {
+    if app.graphics.protocol != GraphicsProtocol::Blocks {
+        let cell_size = graphics::cell_size();
+        if let Some((cols, rows)) = app
+            .images
+            .size_in_cells(url, max_width, max_height, cell_size)
+        {
+            let blank = vec![Spans::from(Span::raw("")); rows as usize];
+            return (blank, Some((cols, rows)));
+        }
+    }
+
+    (app.images.preview(url, max_width, max_height), None)
+}
+
+/// E.g. `Alice and Bob are typing…`
+fn typing_line(names: &[&str]) -> String {
+    match names {
+        [] => String::new(),
+        [name] => format!("{} is typing…", name),
+        [first, second] => format!("{} and {} are typing…", first, second),
+        [first, second, third] => format!("{}, {} and {} are typing…", first, second, third),
+        [first, second, rest @ ..] => format!(
+            "{}, {} and {} others are typing…",
+            first,
+            second,
+            rest.len()
+        ),
+    }
+}
+
+/// Whether what is in the input box is a message being written, rather
+/// than nothing, a command or an edit
+fn composing(input_mode: &InputMode, input: &editor::Editor) -> bool {
+    matches!(input_mode, InputMode::Editing) && !input.is_empty() && !input.starts_with('/')
+}
+
+/// Tells the server whether we are typing in `channel`, based on what is
+/// in the input box, without sending `BeginTyping` too often.
+fn update_typing(
+    events: &Events,
+    channel: ChannelId,
+    typing_sent: &mut Option<Instant>,
+    composing: bool,
+) {
+    match (composing, *typing_sent) {
+        (true, Some(sent)) if sent.elapsed() < TYPING_RESEND_INTERVAL => {}
+        (true, _) => {
+            events.send_client_event(ClientToServerEvent::BeginTyping { channel });
+            *typing_sent = Some(Instant::now());
+        }
+        (false, Some(_)) => {
+            events.send_client_event(ClientToServerEvent::EndTyping { channel });
+            *typing_sent = None;
+        }
+        (false, None) => {}
+    }
+}
+
 pub fn render(app: &AppState, f: &mut Frame<B>) {
     let [server_list_container, main_container] = Layout::default()
         .direction(Direction::Horizontal)
@@ -167,7 +1182,17 @@ pub fn render(app: &AppState, f: &mut Frame<B>) {
             server_list
                 .iter()
                 .map(|server| {
-                    let content = vec![Spans::from(Span::raw(&server.name))];
+                    let unread = app.unreads.is_server_unread(server);
+                    let badge = unreads::badge(unread, app.unreads.server_mention_count(server));
+                    let style = if unread {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default()
+                    };
+                    let content = vec![Spans::from(vec![
+                        Span::styled(badge, Style::default().fg(Color::LightRed)),
+                        Span::styled(&server.name, style),
+                    ])];
                     ListItem::new(content)
                 })
                 .collect()
@@ -179,25 +1204,52 @@ pub fn render(app: &AppState, f: &mut Frame<B>) {
     match &app.state {
         AppStateInternal::ServerChannel {
             input,
+            recalled: _,
+            completion,
             input_mode,
             messages,
+            referenced_messages,
+            invites,
+            first_unread,
+            typing,
+            typing_sent: _,
+            replying_to,
+            attaching,
             current_channel,
             server,
             server_channels,
+            popup,
+            status,
         } => {
-            let [server_bar, inner_container, _members_list] = Layout::default()
+            let members_width = if app.show_members { 10 } else { 0 };
+            let [server_bar, inner_container, members_list] = Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints(
                     [
                         Constraint::Percentage(10),
-                        Constraint::Percentage(80),
-                        Constraint::Percentage(10),
+                        Constraint::Percentage(90 - members_width),
+                        Constraint::Percentage(members_width),
                     ]
                     .as_ref(),
                 )
                 .split(main_container)
                 .to_array();
 
+            if app.show_members {
+                let items = match app.members.get(&server.id) {
+                    Some(Some(member_list)) => {
+                        member_list.visible_items(members_list.height.saturating_sub(2) as usize)
+                    }
+                    _ => vec![ListItem::new(Span::styled(
+                        "Loading…",
+                        Style::default().fg(Color::DarkGray),
+                    ))],
+                };
+                let members_p =
+                    List::new(items).block(Block::default().borders(Borders::ALL).title("Members"));
+                f.render_widget(members_p, members_list);
+            }
+
             let [server_bar_header, channels_list_container] = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([Constraint::Percentage(20), Constraint::Percentage(80)].as_ref())
@@ -222,25 +1274,51 @@ pub fn render(app: &AppState, f: &mut Frame<B>) {
             let channels: Vec<ListItem> = server_channels
                 .iter()
                 .map(|channel| {
-                    let content = vec![Spans::from(Span::raw(channel.name().unwrap().clone()))];
+                    let unread = app.unreads.is_unread(channel.id());
+                    let badge = unreads::badge(unread, app.unreads.mention_count(channel.id()));
+                    // the draft of the open channel is in the input box
+                    let draft = if channel.id() != current_channel.id()
+                        && app.drafts.get(channel.id()).is_some()
+                    {
+                        " ✎"
+                    } else {
+                        ""
+                    };
+                    let style = if unread {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default()
+                    };
+                    let content = vec![Spans::from(vec![
+                        Span::styled(badge, Style::default().fg(Color::LightRed)),
+                        Span::styled(channel.name().unwrap().clone(), style),
+                        Span::styled(draft, Style::default().fg(Color::DarkGray)),
+                    ])];
                     ListItem::new(content)
                 })
                 .collect();
             let channels = List::new(channels).block(Block::default().borders(Borders::ALL));
             f.render_widget(channels, channels_list_container);
 
-            let [channel_header, messages_container, input_container] = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints(
-                    [
-                        Constraint::Length(3),
-                        Constraint::Min(3),
-                        Constraint::Length(3),
-                    ]
-                    .as_ref(),
-                )
-                .split(inner_container)
-                .to_array();
+            let input_height = input.line_count().min(MAX_INPUT_LINES) as u16 + 2;
+            let compose_bar_height = !typing.is_empty() as u16
+                + replying_to.is_some() as u16
+                + attaching.is_some() as u16;
+            let [channel_header, messages_container, compose_bar, input_container, status_container] =
+                Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints(
+                        [
+                            Constraint::Length(3),
+                            Constraint::Min(3),
+                            Constraint::Length(compose_bar_height),
+                            Constraint::Length(input_height),
+                            Constraint::Length(1),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(inner_container)
+                    .to_array();
 
             let channel_desc_p = Paragraph::new(
                 current_channel
@@ -261,51 +1339,566 @@ pub fn render(app: &AppState, f: &mut Frame<B>) {
             );
             f.render_widget(channel_desc_p, channel_header);
 
-            let messages: Vec<ListItem> = messages
+            let mut messages_state = ListState::default();
+            if let InputMode::Selecting { selected } = input_mode {
+                messages_state.select(Some(messages.len() - 1 - selected));
+            }
+
+            // (item, line in item, url, size) of the images left for `draw_graphics`
+            let mut pending_placements = Vec::new();
+
+            let message_items: Vec<ListItem> = messages
                 .iter()
                 .rev()
-                .map(|message| {
-                    let content = vec![Spans::from(Span::raw(format!(
+                .enumerate()
+                .map(|(item, message)| {
+                    let mut content: Vec<Spans> = Vec::new();
+                    if Some(message.0.id) == *first_unread {
+                        content.push(new_divider(messages_container.width.saturating_sub(2)));
+                    }
+                    content.extend(
+                        message
+                            .0
+                            .replies
+                            .iter()
+                            .flatten()
+                            .map(|id| reply_preview(id, messages, referenced_messages)),
+                    );
+                    content.push(Spans::from(Span::raw(format!(
                         "{}: {:?}",
                         message.1.display_name(),
                         message.0.content
-                    )))];
+                    ))));
+                    for attachment in message.0.attachments.iter().flatten() {
+                        content.push(Spans::from(Span::styled(
+                            format!("  📎 {}", attachment_line(attachment)),
+                            Style::default().fg(Color::Cyan),
+                        )));
+
+                        if app.images.enabled && is_image(attachment) {
+                            let url = attachment_url(attachment);
+                            let (lines, size) = image_preview(
+                                app,
+                                &url,
+                                messages_container.width.saturating_sub(4),
+                                messages_container.height / 2,
+                            );
+
+                            if let Some(size) = size {
+                                pending_placements.push((item, content.len() as u16, url, size));
+                            }
+                            content.extend(lines);
+                        }
+                    }
+
+                    let width = messages_container.width.saturating_sub(2) as usize;
+                    for embed in message.0.embeds.iter().flatten() {
+                        content.extend(embeds::embed_lines(embed, width));
+
+                        if let Some(url) = embeds::embed_image(embed).filter(|_| app.images.enabled)
+                        {
+                            let url = url.to_string();
+                            let (lines, size) = image_preview(
+                                app,
+                                &url,
+                                messages_container.width.saturating_sub(4),
+                                messages_container.height / 2,
+                            );
+
+                            if let Some(size) = size {
+                                pending_placements.push((item, content.len() as u16, url, size));
+                            }
+                            content.extend(lines);
+                        }
+                    }
+
+                    for code in embeds::invite_codes(message_text(&message.0)) {
+                        content.extend(embeds::invite_lines(code, invites.get(code), width));
+                    }
+
                     ListItem::new(content)
                 })
                 .collect();
-            let messages = List::new(messages)
+            let messages_list_area = Block::default()
+                .borders(Borders::ALL)
+                .inner(messages_container);
+            let heights: Vec<usize> = message_items.iter().map(ListItem::height).collect();
+            let tops =
+                util::list_item_tops(&heights, messages_state.selected(), messages_list_area);
+
+            // images are drawn over everything, so they would cover the popups
+            if popup.is_none() {
+                let mut placements = app.placements.borrow_mut();
+                for (item, line, url, (cols, rows)) in pending_placements {
+                    if let Some(top) = tops[item] {
+                        placements.push(Placement {
+                            url,
+                            area: Rect::new(messages_list_area.x + 2, top + line, cols, rows),
+                        });
+                    }
+                }
+            }
+
+            let messages_list = List::new(message_items)
                 .block(Block::default().borders(Borders::ALL))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
                 .start_corner(Corner::BottomLeft);
-            f.render_widget(messages, messages_container);
+            f.render_stateful_widget(messages_list, messages_container, &mut messages_state);
 
-            let input_p = Paragraph::new(input.as_ref())
+            let mut compose_bar_lines = Vec::new();
+            if !typing.is_empty() {
+                let names: Vec<&str> = typing.iter().map(|(_, name, _)| name.as_str()).collect();
+                compose_bar_lines.push(Spans::from(Span::styled(
+                    typing_line(&names),
+                    Style::default()
+                        .fg(Color::Gray)
+                        .add_modifier(Modifier::ITALIC),
+                )));
+            }
+            if let Some(reply) = replying_to {
+                compose_bar_lines.push(Spans::from(vec![
+                    Span::raw("Replying to "),
+                    Span::styled(
+                        reply.author_name.as_str(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        if reply.mention {
+                            "  [@ on, Alt-m to toggle]"
+                        } else {
+                            "  [@ off, Alt-m to toggle]"
+                        },
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]));
+            }
+            if let Some(path) = attaching {
+                compose_bar_lines.push(Spans::from(vec![
+                    Span::raw("Attaching "),
+                    Span::styled(
+                        path.display().to_string(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        "  [type a caption, Esc to remove]",
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]));
+            }
+            f.render_widget(Paragraph::new(compose_bar_lines), compose_bar);
+
+            // scrolled so the cursor stays in the box
+            let input_scroll = (input.cursor_column() as u16)
+                .saturating_sub(input_container.width.saturating_sub(3));
+            let input_scroll_lines =
+                (input.cursor_line() as u16).saturating_sub(input_height.saturating_sub(3));
+            let input_p = Paragraph::new(&**input)
                 .style(match input_mode {
-                    InputMode::Normal => Style::default(),
-                    InputMode::Editing => Style::default().fg(Color::Yellow),
+                    InputMode::Normal | InputMode::Selecting { .. } => Style::default(),
+                    InputMode::Editing | InputMode::SearchingHistory { .. } => {
+                        Style::default().fg(Color::Yellow)
+                    }
+                    InputMode::EditingMessage { .. } => Style::default().fg(Color::LightGreen),
                 })
-                .block(Block::default().borders(Borders::ALL).title("Input"));
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(match input_mode {
+                            InputMode::EditingMessage { .. } => {
+                                "Edit message (Esc to cancel)".to_string()
+                            }
+                            InputMode::SearchingHistory { query, found, .. } => format!(
+                                "History search{}: {}",
+                                if found.is_none() && !query.is_empty() {
+                                    " (no match)"
+                                } else {
+                                    ""
+                                },
+                                query
+                            ),
+                            InputMode::Editing if input.is_empty() => {
+                                "Input (Up: edit your last message, Ctrl-P: history)".to_string()
+                            }
+                            _ => "Input".to_string(),
+                        }),
+                )
+                .scroll((input_scroll_lines, input_scroll));
             f.render_widget(input_p, input_container);
             match input_mode {
-                InputMode::Normal =>
+                InputMode::Normal | InputMode::Selecting { .. } =>
                     // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
                     {}
 
-                InputMode::Editing => {
+                InputMode::Editing
+                | InputMode::EditingMessage { .. }
+                | InputMode::SearchingHistory { .. } => {
                     // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
                     f.set_cursor(
-                        // Put cursor past the end of the input text
-                        input_container.x + input.width() as u16 + 1,
-                        // Move one line down, from the border to the input line
-                        input_container.y + 1,
+                        // Put the cursor where it is in the input text
+                        input_container.x + input.cursor_column() as u16 - input_scroll + 1,
+                        // Move down from the border to the line of the cursor
+                        input_container.y + input.cursor_line() as u16 - input_scroll_lines + 1,
                     )
                 }
             }
+
+            if let Some(completion) = completion {
+                let height = completion.candidates.len().min(MAX_COMPLETION_ROWS) as u16 + 2;
+                let width = completion
+                    .candidates
+                    .iter()
+                    .map(|candidate| candidate.label.width() as u16 + 2)
+                    .max()
+                    .unwrap_or(0)
+                    .min(f.size().width);
+                // over the input box, from the cursor
+                let column = input_container.x + 1 + input.cursor_column() as u16 - input_scroll;
+                let area = Rect {
+                    x: column.min(f.size().width - width),
+                    y: input_container.y.saturating_sub(height),
+                    width,
+                    height,
+                };
+
+                let items: Vec<ListItem> = completion
+                    .candidates
+                    .iter()
+                    .map(|candidate| ListItem::new(candidate.label.as_str()))
+                    .collect();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                let mut list_state = ListState::default();
+                list_state.select(Some(completion.selected));
+
+                f.render_widget(Clear, area);
+                f.render_stateful_widget(list, area, &mut list_state);
+            }
+
+            if let Some(status) = status {
+                let status_p = match status {
+                    Status::Info(text) => Paragraph::new(text.as_str()),
+                    Status::Error(text) => {
+                        Paragraph::new(text.as_str()).style(Style::default().fg(Color::Red))
+                    }
+                };
+                f.render_widget(status_p, status_container);
+            }
+
+            if let Some(popup) = popup {
+                match popup {
+                    Popup::ConfirmDelete { index } => {
+                        let (message, author) = &messages[*index];
+                        let area = util::centered_rect(50, 20, f.size());
+                        let text = vec![
+                            Spans::from(Span::raw("Delete this message?")),
+                            Spans::from(Span::raw(format!(
+                                "{}: {}",
+                                author.display_name(),
+                                message_text(message)
+                            ))),
+                            Spans::from(Span::styled(
+                                "[y]es / [n]o",
+                                Style::default().add_modifier(Modifier::BOLD),
+                            )),
+                        ];
+                        let popup_p = Paragraph::new(text).block(
+                            Block::default()
+                                .borders(Borders::ALL)
+                                .title("Delete message"),
+                        );
+                        f.render_widget(Clear, area);
+                        f.render_widget(popup_p, area);
+                    }
+                    Popup::ConfirmBlock { user } => {
+                        let area = util::centered_rect(50, 20, f.size());
+                        let text = vec![
+                            Spans::from(Span::raw(format!(
+                                "Block {}? They will not be able to message you.",
+                                user.user.username
+                            ))),
+                            Spans::from(Span::styled(
+                                "[y]es / [n]o",
+                                Style::default().add_modifier(Modifier::BOLD),
+                            )),
+                        ];
+                        let popup_p = Paragraph::new(text)
+                            .block(Block::default().borders(Borders::ALL).title("Block user"));
+                        f.render_widget(Clear, area);
+                        f.render_widget(popup_p, area);
+                    }
+                    Popup::MessageActions { selected, .. } => {
+                        let area = util::centered_rect(30, 40, f.size());
+                        let actions: Vec<ListItem> = MessageAction::ALL
+                            .iter()
+                            .map(|action| ListItem::new(action.label()))
+                            .collect();
+                        let mut actions_state = ListState::default();
+                        actions_state.select(Some(*selected));
+                        let actions = List::new(actions)
+                            .block(Block::default().borders(Borders::ALL).title("Actions"))
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(actions, area, &mut actions_state);
+                    }
+                    Popup::Attachments { index, selected } => {
+                        let area = util::centered_rect(50, 40, f.size());
+                        let attachments: Vec<ListItem> = messages[*index]
+                            .0
+                            .attachments
+                            .iter()
+                            .flatten()
+                            .map(|attachment| ListItem::new(attachment_line(attachment)))
+                            .collect();
+                        let mut attachments_state = ListState::default();
+                        attachments_state.select(Some(*selected));
+                        let attachments = List::new(attachments)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Attachments (Enter: download, o: open)"),
+                            )
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(attachments, area, &mut attachments_state);
+                    }
+                    Popup::FilePicker {
+                        dir,
+                        entries,
+                        selected,
+                    } => {
+                        let area = util::centered_rect(50, 60, f.size());
+                        let entries: Vec<ListItem> = entries
+                            .iter()
+                            .map(|entry| {
+                                if entry.is_dir {
+                                    ListItem::new(format!("{}/", entry.name))
+                                        .style(Style::default().fg(Color::LightBlue))
+                                } else {
+                                    ListItem::new(entry.name.as_str())
+                                }
+                            })
+                            .collect();
+                        let mut entries_state = ListState::default();
+                        entries_state.select(Some(*selected));
+                        let entries = List::new(entries)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title(dir.display().to_string()),
+                            )
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(entries, area, &mut entries_state);
+                    }
+                    Popup::Profile { user } => {
+                        let area = util::centered_rect(50, 70, f.size());
+                        let mut text = Vec::new();
+                        if let Some(avatar) = &user.user.avatar {
+                            if app.images.enabled {
+                                let url = avatar_url(avatar);
+                                let (lines, size) = image_preview(
+                                    app,
+                                    &url,
+                                    area.width.saturating_sub(4),
+                                    area.height / 2,
+                                );
+
+                                if let Some((cols, rows)) = size {
+                                    // below the top border, after the indent
+                                    app.placements.borrow_mut().push(Placement {
+                                        url,
+                                        area: Rect::new(area.x + 3, area.y + 1, cols, rows),
+                                    });
+                                }
+                                text.extend(lines);
+                            }
+                        }
+                        text.extend(profile::lines(
+                            user,
+                            app.profiles.get(&user.user.id).and_then(Option::as_ref),
+                            server,
+                            app.server_list.as_deref().unwrap_or_default(),
+                        ));
+                        let profile_p = Paragraph::new(text)
+                            .block(Block::default().borders(Borders::ALL).title("Profile"))
+                            .wrap(Wrap { trim: false });
+                        f.render_widget(Clear, area);
+                        f.render_widget(profile_p, area);
+                    }
+                    Popup::Inbox { selected } => {
+                        let area = util::centered_rect(60, 60, f.size());
+                        let mentions: Vec<ListItem> = app
+                            .inbox
+                            .iter()
+                            .map(|mention| {
+                                ListItem::new(vec![
+                                    Spans::from(vec![
+                                        Span::styled(
+                                            mention.place.as_str(),
+                                            Style::default().fg(Color::DarkGray),
+                                        ),
+                                        Span::raw(" "),
+                                        Span::styled(
+                                            mention.author.display_name().to_string(),
+                                            Style::default().add_modifier(Modifier::BOLD),
+                                        ),
+                                    ]),
+                                    Spans::from(Span::raw(
+                                        message_text(&mention.message)
+                                            .lines()
+                                            .next()
+                                            .unwrap_or("")
+                                            .to_string(),
+                                    )),
+                                ])
+                            })
+                            .collect();
+                        let mut mentions_state = ListState::default();
+                        mentions_state.select(Some(*selected).filter(|_| !app.inbox.is_empty()));
+                        let mentions = List::new(mentions)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Mentions (Enter: jump, d: dismiss)"),
+                            )
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(mentions, area, &mut mentions_state);
+                    }
+                    Popup::DirectMessages { selected } => {
+                        let area = util::centered_rect(40, 60, f.size());
+                        let mut conversations_state = ListState::default();
+                        conversations_state
+                            .select(Some(*selected).filter(|_| !app.direct_messages.is_empty()));
+                        let conversations = List::new(app.direct_messages.items())
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Direct messages (Enter: open in browser)"),
+                            )
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(conversations, area, &mut conversations_state);
+                    }
+                    Popup::ServerInfo { owner, scroll } => {
+                        let area = util::centered_rect(60, 70, f.size());
+                        let member_count = match app.members.get(&server.id) {
+                            Some(Some(member_list)) => Some(member_list.member_count()),
+                            _ => None,
+                        };
+                        let lines =
+                            info::server_lines(server, server_channels, owner, member_count);
+                        clamp_scroll(scroll, &lines, area);
+                        let info_p = Paragraph::new(lines)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Server info (j/k: scroll)"),
+                            )
+                            .wrap(Wrap { trim: false })
+                            .scroll((scroll.get(), 0));
+                        f.render_widget(Clear, area);
+                        f.render_widget(info_p, area);
+                    }
+                    Popup::ChannelInfo { scroll } => {
+                        let area = util::centered_rect(60, 50, f.size());
+                        let lines = info::channel_lines(current_channel);
+                        clamp_scroll(scroll, &lines, area);
+                        let info_p = Paragraph::new(lines)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Channel topic (j/k: scroll)"),
+                            )
+                            .wrap(Wrap { trim: false })
+                            .scroll((scroll.get(), 0));
+                        f.render_widget(Clear, area);
+                        f.render_widget(info_p, area);
+                    }
+                    Popup::SetStatus { selected, text } => {
+                        let area = util::centered_rect(40, 30, f.size());
+                        let mut lines: Vec<Spans> = presence::CHOICES
+                            .iter()
+                            .enumerate()
+                            .map(|(i, choice)| {
+                                let style = if i == *selected {
+                                    Style::default().add_modifier(Modifier::REVERSED)
+                                } else {
+                                    Style::default()
+                                };
+                                Spans::from(vec![
+                                    Span::styled(
+                                        "● ",
+                                        Style::default().fg(presence::color(true, Some(*choice))),
+                                    ),
+                                    Span::styled(presence::label(*choice), style),
+                                ])
+                            })
+                            .collect();
+                        lines.push(Spans::from(Span::raw("")));
+                        lines.push(Spans::from(vec![
+                            Span::styled("Status: ", Style::default().fg(Color::Gray)),
+                            Span::raw(text.as_str()),
+                        ]));
+                        let status_p = Paragraph::new(lines).block(
+                            Block::default()
+                                .borders(Borders::ALL)
+                                .title("Set status (↑↓: presence, Enter: save)"),
+                        );
+                        f.render_widget(Clear, area);
+                        f.render_widget(status_p, area);
+                    }
+                }
+            }
         }
     }
 }
 
+/// Draws the images `render` left room for over the frame it rendered,
+/// when the terminal can show images itself.
+pub fn draw_graphics(app: &mut AppState, terminal: &mut Terminal<B>) -> io::Result<()> {
+    if app.graphics.protocol == GraphicsProtocol::Blocks {
+        return Ok(());
+    }
+
+    let placements = app.placements.take();
+
+    if app.graphics.protocol == GraphicsProtocol::Sixel && app.graphics.changed(&placements) {
+        // sixels stay on screen until something is drawn over them, and tui
+        // only redraws what it thinks changed, so get rid of the old ones
+        terminal.clear()?;
+        terminal.draw(|f| render(app, f))?;
+        app.placements.take();
+    }
+
+    app.graphics
+        .draw(terminal.backend_mut(), placements, &app.images)
+}
+
+/// Edits the input of the open channel in an external editor, see
+/// `editor::edit_externally`.
+///
+/// The caller gives the terminal to the editor before, and sets it up
+/// again after.
+pub fn compose_externally(app: &mut AppState) {
+    if let AppStateInternal::ServerChannel { input, status, .. } = &mut app.state {
+        match editor::edit_externally(input) {
+            Ok(text) => input.set(text),
+            Err(e) => {
+                *status = Some(Status::Error(format!("Could not run the editor: {}", e)));
+            }
+        }
+    }
+
+    // the screen is drawn again from scratch
+    app.graphics.forget_placements();
+}
+
 pub enum Action {
     Break,
+    /// Suspend the terminal and call `compose_externally`
+    ComposeExternally,
     None,
 }
 
@@ -316,62 +1909,1706 @@ pub async fn update(app: &mut AppState, events: &mut Events) -> Action {
             state,
             ctx,
             server_list,
+            me,
+            presence,
+            status_text,
+            last_input,
+            auto_idle,
+            config,
+            images,
+            graphics: _,
+            placements: _,
+            unreads,
+            inbox,
+            direct_messages,
+            members,
+            show_members,
+            profiles,
+            history,
+            drafts,
+            recent_completions,
+            opening,
         } = app;
 
+        // channel to load and switch to once the event is handled
+        let mut open = None;
+        // loaded channel to switch to, with the message to select in it
+        let mut switch_to = None;
+
         match state {
             AppStateInternal::ServerChannel {
                 input,
+                recalled,
+                completion,
                 input_mode,
                 messages,
+                referenced_messages,
+                invites,
+                first_unread,
+                typing,
+                typing_sent,
+                replying_to,
+                attaching,
                 current_channel: current,
-                server: _,
-                server_channels: _,
+                server,
+                server_channels,
+                popup,
+                status,
             } => match ev {
-                Event::Input(input_key) => match input_mode {
-                    InputMode::Normal => match input_key {
-                        Key::Char('e') => {
-                            *input_mode = InputMode::Editing;
-                        }
-                        Key::Char('q') => {
-                            return Action::Break;
+                Event::Input(input_key) => {
+                    *status = None;
+
+                    note_activity(ctx, last_input, auto_idle, presence, status_text);
+
+                    let mut message_action = None;
+                    let mut command = None;
+
+                    if let Some(current_popup) = popup.take() {
+                        match current_popup {
+                            Popup::ConfirmDelete { index } => match input_key {
+                                Key::Char('y') | Key::Char('\n') => {
+                                    let message = messages.remove(index);
+                                    clamp_selection(input_mode, messages.len());
+
+                                    let channel = message.0.channel;
+                                    let message_id = message.0.id;
+                                    let http = Arc::clone(&ctx.http);
+                                    let tx = events.sender();
+                                    tokio::spawn(async move {
+                                        if let Err(e) =
+                                            http.delete_message(channel, message_id).await
+                                        {
+                                            let _ = tx.send(Event::MessageDeleteFailed {
+                                                message: Box::new(message),
+                                                error: e.to_string(),
+                                            });
+                                        }
+                                    });
+                                }
+                                Key::Char('n') | Key::Esc => {}
+                                _ => *popup = Some(current_popup),
+                            },
+                            Popup::MessageActions { index, selected } => match input_key {
+                                Key::Up | Key::Char('k') => {
+                                    *popup = Some(Popup::MessageActions {
+                                        index,
+                                        selected: selected.saturating_sub(1),
+                                    });
+                                }
+                                Key::Down | Key::Char('j') => {
+                                    *popup = Some(Popup::MessageActions {
+                                        index,
+                                        selected: (selected + 1).min(MessageAction::ALL.len() - 1),
+                                    });
+                                }
+                                Key::Char('\n') => {
+                                    message_action = Some((index, MessageAction::ALL[selected]));
+                                }
+                                Key::Esc | Key::Char('q') => {}
+                                _ => *popup = Some(current_popup),
+                            },
+                            Popup::Attachments { index, selected } => {
+                                let attachments = messages[index].0.attachments.as_deref();
+                                let attachments = attachments.unwrap_or_default();
+
+                                match input_key {
+                                    Key::Up | Key::Char('k') => {
+                                        *popup = Some(Popup::Attachments {
+                                            index,
+                                            selected: selected.saturating_sub(1),
+                                        });
+                                    }
+                                    Key::Down | Key::Char('j') => {
+                                        *popup = Some(Popup::Attachments {
+                                            index,
+                                            selected: (selected + 1)
+                                                .min(attachments.len().saturating_sub(1)),
+                                        });
+                                    }
+                                    Key::Char('\n') => {
+                                        let attachment = &attachments[selected];
+                                        transfers::spawn_download(
+                                            attachment_url(attachment),
+                                            &attachment.filename,
+                                            config.download_dir.clone(),
+                                            events.sender(),
+                                        );
+                                    }
+                                    Key::Char('o') => {
+                                        let url = attachment_url(&attachments[selected]);
+                                        if let Err(e) = util::open_url(&url) {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not open attachment: {}",
+                                                e
+                                            )));
+                                        }
+                                    }
+                                    Key::Esc | Key::Char('q') => {}
+                                    _ => *popup = Some(current_popup),
+                                }
+                            }
+                            Popup::FilePicker {
+                                dir,
+                                entries,
+                                selected,
+                            } => match input_key {
+                                Key::Up | Key::Char('k') => {
+                                    *popup = Some(Popup::FilePicker {
+                                        dir,
+                                        entries,
+                                        selected: selected.saturating_sub(1),
+                                    });
+                                }
+                                Key::Down | Key::Char('j') => {
+                                    let selected =
+                                        (selected + 1).min(entries.len().saturating_sub(1));
+                                    *popup = Some(Popup::FilePicker {
+                                        dir,
+                                        entries,
+                                        selected,
+                                    });
+                                }
+                                Key::Char('\n') if entries.is_empty() => {
+                                    *popup = Some(Popup::FilePicker {
+                                        dir,
+                                        entries,
+                                        selected,
+                                    });
+                                }
+                                Key::Char('\n') if entries[selected].is_dir => {
+                                    let target = match entries[selected].name.as_str() {
+                                        ".." => {
+                                            dir.parent().map_or(dir.clone(), |it| it.to_owned())
+                                        }
+                                        name => dir.join(name),
+                                    };
+
+                                    match transfers::list_dir(&target) {
+                                        Ok(entries) => {
+                                            *popup = Some(Popup::FilePicker {
+                                                dir: target,
+                                                entries,
+                                                selected: 0,
+                                            });
+                                        }
+                                        Err(e) => {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not open {}: {}",
+                                                target.display(),
+                                                e
+                                            )));
+                                            *popup = Some(Popup::FilePicker {
+                                                dir,
+                                                entries,
+                                                selected,
+                                            });
+                                        }
+                                    }
+                                }
+                                Key::Char('\n') => {
+                                    *attaching = Some(dir.join(&entries[selected].name));
+                                    *input_mode = InputMode::Editing;
+                                }
+                                Key::Esc | Key::Char('q') => {}
+                                _ => {
+                                    *popup = Some(Popup::FilePicker {
+                                        dir,
+                                        entries,
+                                        selected,
+                                    });
+                                }
+                            },
+                            Popup::Profile { ref user } => match input_key {
+                                Key::Char('m') => match ctx.http.open_dm(user.user.id).await {
+                                    // direct messages cannot be shown here yet
+                                    Ok(channel) => {
+                                        let url = format!("{}/channel/{}", APP_URL, channel.id());
+                                        if let Err(e) = util::open_url(&url) {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not open the conversation: {}",
+                                                e
+                                            )));
+                                        }
+                                        *popup = Some(current_popup);
+                                    }
+                                    Err(e) => {
+                                        *status = Some(Status::Error(format!(
+                                            "Could not open the conversation: {}",
+                                            e
+                                        )));
+                                        *popup = Some(current_popup);
+                                    }
+                                },
+                                Key::Char('f') => {
+                                    *status = Some(
+                                        match ctx
+                                            .http
+                                            .send_friend_request(&user.user.username)
+                                            .await
+                                        {
+                                            Ok(_) => Status::Info(format!(
+                                                "Sent a friend request to {}",
+                                                user.user.username
+                                            )),
+                                            Err(e) => Status::Error(format!(
+                                                "Could not send the friend request: {}",
+                                                e
+                                            )),
+                                        },
+                                    );
+                                    *popup = Some(current_popup);
+                                }
+                                Key::Char('b') => {
+                                    *popup = Some(Popup::ConfirmBlock { user: user.clone() });
+                                }
+                                Key::Esc | Key::Char('q') => {}
+                                _ => *popup = Some(current_popup),
+                            },
+                            Popup::ConfirmBlock { user } => match input_key {
+                                Key::Char('y') | Key::Char('\n') => {
+                                    *status = Some(match ctx.http.block_user(user.user.id).await {
+                                        Ok(_) => {
+                                            Status::Info(format!("Blocked {}", user.user.username))
+                                        }
+                                        Err(e) => Status::Error(format!("Could not block: {}", e)),
+                                    });
+                                    *popup = Some(Popup::Profile { user });
+                                }
+                                Key::Char('n') | Key::Esc => {
+                                    *popup = Some(Popup::Profile { user });
+                                }
+                                _ => *popup = Some(Popup::ConfirmBlock { user }),
+                            },
+                            Popup::Inbox { selected } => match input_key {
+                                Key::Up | Key::Char('k') => {
+                                    *popup = Some(Popup::Inbox {
+                                        selected: selected.saturating_sub(1),
+                                    });
+                                }
+                                Key::Down | Key::Char('j') => {
+                                    *popup = Some(Popup::Inbox {
+                                        selected: (selected + 1).min(inbox.len().saturating_sub(1)),
+                                    });
+                                }
+                                Key::Char('d') => {
+                                    inbox.remove(selected);
+                                    *popup = Some(Popup::Inbox {
+                                        selected: selected.min(inbox.len().saturating_sub(1)),
+                                    });
+                                }
+                                Key::Char('\n') => match inbox.get(selected) {
+                                    Some(mention) if mention.message.channel == current.id() => {
+                                        let index = messages
+                                            .iter()
+                                            .position(|(m, _)| m.id == mention.message.id);
+
+                                        match index {
+                                            Some(index) => {
+                                                *input_mode =
+                                                    InputMode::Selecting { selected: index };
+                                            }
+                                            None => {
+                                                *status = Some(Status::Error(
+                                                    "The message is too old to be loaded"
+                                                        .to_string(),
+                                                ));
+                                            }
+                                        }
+                                    }
+                                    Some(mention) => {
+                                        open = Some((
+                                            mention.message.channel,
+                                            Some(mention.message.id),
+                                        ));
+                                    }
+                                    None => *popup = Some(current_popup),
+                                },
+                                Key::Esc | Key::Char('q') => {}
+                                _ => *popup = Some(current_popup),
+                            },
+                            Popup::DirectMessages { selected } => match input_key {
+                                Key::Up | Key::Char('k') => {
+                                    *popup = Some(Popup::DirectMessages {
+                                        selected: selected.saturating_sub(1),
+                                    });
+                                }
+                                Key::Down | Key::Char('j') => {
+                                    *popup = Some(Popup::DirectMessages {
+                                        selected: (selected + 1)
+                                            .min(direct_messages.len().saturating_sub(1)),
+                                    });
+                                }
+                                // direct messages cannot be shown here yet
+                                Key::Char('\n') => {
+                                    if let Some(channel) = direct_messages.channel(selected) {
+                                        let url = format!("{}/channel/{}", APP_URL, channel);
+                                        if let Err(e) = util::open_url(&url) {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not open the conversation: {}",
+                                                e
+                                            )));
+                                        }
+                                    }
+                                    *popup = Some(current_popup);
+                                }
+                                Key::Esc | Key::Char('q') => {}
+                                _ => *popup = Some(current_popup),
+                            },
+                            Popup::ServerInfo { owner, scroll } => match input_key {
+                                Key::Esc | Key::Char('q') => {}
+                                _ => {
+                                    *popup = Some(Popup::ServerInfo {
+                                        owner,
+                                        scroll: Cell::new(scroll_info(scroll.get(), input_key)),
+                                    });
+                                }
+                            },
+                            Popup::ChannelInfo { scroll } => match input_key {
+                                Key::Esc | Key::Char('q') => {}
+                                _ => {
+                                    *popup = Some(Popup::ChannelInfo {
+                                        scroll: Cell::new(scroll_info(scroll.get(), input_key)),
+                                    });
+                                }
+                            },
+                            Popup::SetStatus { selected, mut text } => match input_key {
+                                Key::Up => {
+                                    *popup = Some(Popup::SetStatus {
+                                        selected: selected.saturating_sub(1),
+                                        text,
+                                    });
+                                }
+                                Key::Down => {
+                                    *popup = Some(Popup::SetStatus {
+                                        selected: (selected + 1).min(presence::CHOICES.len() - 1),
+                                        text,
+                                    });
+                                }
+                                Key::Char('\n') => {
+                                    let chosen = presence::CHOICES[selected];
+                                    let text = Some(text).filter(|text| !text.trim().is_empty());
+
+                                    match set_status(ctx, chosen, text.clone()).await {
+                                        Ok(_) => {
+                                            *presence = chosen;
+                                            *status_text = text;
+                                            *auto_idle = false;
+                                            *status = Some(Status::Info(format!(
+                                                "You are now {}",
+                                                presence::label(chosen)
+                                            )));
+                                        }
+                                        Err(e) => {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not set status: {}",
+                                                e
+                                            )));
+                                        }
+                                    }
+                                }
+                                Key::Char(c) => {
+                                    text.push(c);
+                                    *popup = Some(Popup::SetStatus { selected, text });
+                                }
+                                Key::Backspace => {
+                                    text.pop();
+                                    *popup = Some(Popup::SetStatus { selected, text });
+                                }
+                                Key::Esc => {}
+                                _ => *popup = Some(Popup::SetStatus { selected, text }),
+                            },
                         }
-                        _ => {}
-                    },
-                    InputMode::Editing => match input_key {
-                        Key::Char('\n') => {
-                            let message = std::mem::take(input);
+                    } else {
+                        match input_mode {
+                            InputMode::Normal => match input_key {
+                                Key::Char('e') => {
+                                    *input_mode = InputMode::Editing;
+                                }
+                                Key::Char('p') => {
+                                    images.enabled = !images.enabled;
+
+                                    for (message, _) in messages.iter() {
+                                        request_images(images, events, message);
+                                    }
+                                }
+                                Key::Char('u') => {
+                                    let index = first_unread.and_then(|first_unread| {
+                                        messages.iter().position(|(m, _)| m.id == first_unread)
+                                    });
+
+                                    match index {
+                                        Some(index) => {
+                                            *input_mode = InputMode::Selecting { selected: index };
+                                        }
+                                        None => {
+                                            *status = Some(Status::Info(
+                                                "No unread messages here".to_string(),
+                                            ));
+                                        }
+                                    }
+                                }
+                                Key::Char('n') => {
+                                    let servers = server_list.as_deref().unwrap_or_default();
+                                    match unreads.next_unread(servers, current.id()) {
+                                        Some(channel) => open = Some((channel, None)),
+                                        None => {
+                                            *status = Some(Status::Info(
+                                                "No unread channels".to_string(),
+                                            ));
+                                        }
+                                    }
+                                }
+                                Key::Char('m') => {
+                                    *show_members = !*show_members;
+                                }
+                                Key::Char('J') | Key::Char('K') => {
+                                    let delta = if input_key == Key::Char('J') { 1 } else { -1 };
+                                    if let Some(Some(member_list)) = members.get_mut(&server.id) {
+                                        member_list.select_by(delta);
+                                    }
+                                }
+                                Key::Char('\n') if *show_members => {
+                                    let selected = match members.get(&server.id) {
+                                        Some(Some(member_list)) => member_list.selected(),
+                                        _ => None,
+                                    };
+
+                                    if let Some(id) = selected {
+                                        let user = id.user(ctx).await;
+                                        let member = ctx.http.fetch_member(server.id, id).await;
+
+                                        match user {
+                                            Ok(user) => {
+                                                let user = UserOptMember {
+                                                    user,
+                                                    member: member.ok(),
+                                                };
+                                                *popup = Some(profile_popup(
+                                                    ctx, images, events, profiles, user,
+                                                ));
+                                            }
+                                            Err(e) => {
+                                                *status = Some(Status::Error(format!(
+                                                    "Could not fetch user: {}",
+                                                    e
+                                                )));
+                                            }
+                                        }
+                                    }
+                                }
+                                Key::Char('i') => {
+                                    *popup = Some(Popup::Inbox { selected: 0 });
+                                }
+                                Key::Char('d') => {
+                                    *popup = Some(Popup::DirectMessages { selected: 0 });
+                                }
+                                Key::Char('o') => {
+                                    let owner = match server.owner.user(ctx).await {
+                                        Ok(owner) => owner.username,
+                                        Err(_) => "Unknown".to_string(),
+                                    };
+                                    *popup = Some(Popup::ServerInfo {
+                                        owner,
+                                        scroll: Cell::new(0),
+                                    });
+                                }
+                                Key::Char('t') => {
+                                    *popup = Some(Popup::ChannelInfo {
+                                        scroll: Cell::new(0),
+                                    });
+                                }
+                                Key::Char('S') => {
+                                    *popup = Some(Popup::SetStatus {
+                                        selected: presence::CHOICES
+                                            .iter()
+                                            .position(|choice| choice == presence)
+                                            .unwrap_or(0),
+                                        text: status_text.clone().unwrap_or_default(),
+                                    });
+                                }
+                                Key::Char('s') if !messages.is_empty() => {
+                                    *input_mode = InputMode::Selecting {
+                                        selected: messages.len() - 1,
+                                    };
+                                }
+                                Key::Char('q') => {
+                                    return Action::Break;
+                                }
+                                _ => {}
+                            },
+                            InputMode::Editing => match input_key {
+                                Key::Char('\t') | Key::Down if completion.is_some() => {
+                                    if let Some(completion) = completion {
+                                        completion.select_by(1);
+                                    }
+                                }
+                                Key::BackTab | Key::Up if completion.is_some() => {
+                                    if let Some(completion) = completion {
+                                        completion.select_by(-1);
+                                    }
+                                }
+                                Key::Char('\n') if completion.is_some() => {
+                                    if let Some(completion) = completion.take() {
+                                        accept_completion(input, completion, recent_completions);
+                                    }
+                                }
+                                Key::Esc if completion.is_some() => {
+                                    *completion = None;
+                                }
+                                Key::Char('\t') if !input.starts_with("/upload ") => {
+                                    *completion = complete(
+                                        input,
+                                        members.get(&server.id).and_then(Option::as_ref),
+                                        messages,
+                                        server_channels,
+                                        recent_completions,
+                                    );
+
+                                    // nothing to choose from
+                                    let only = completion.as_ref().map_or(false, |completion| {
+                                        completion.candidates.len() == 1
+                                    });
+                                    if only {
+                                        if let Some(completion) = completion.take() {
+                                            accept_completion(
+                                                input,
+                                                completion,
+                                                recent_completions,
+                                            );
+                                        }
+                                    }
+                                }
+                                Key::Char('\n') if commands::is_command(input) => {
+                                    match commands::parse(input) {
+                                        Ok(invocation) => {
+                                            let line = input.take();
+                                            *recalled = None;
+                                            if let Err(e) = history.push(current.id(), &line) {
+                                                *status = Some(Status::Error(format!(
+                                                    "Could not save the input history: {}",
+                                                    e
+                                                )));
+                                            }
+                                            command = Some(invocation);
+                                        }
+                                        // kept in the input box to be corrected
+                                        Err(usage) => *status = Some(Status::Error(usage)),
+                                    }
+                                }
+                                Key::Char('\n') if attaching.is_some() => {
+                                    let caption = input.take();
+                                    *recalled = None;
+                                    if let Err(e) = history.push(current.id(), &caption) {
+                                        *status = Some(Status::Error(format!(
+                                            "Could not save the input history: {}",
+                                            e
+                                        )));
+                                    }
+
+                                    let reply = replying_to.take().map(|reply| ReplyData {
+                                        id: reply.message,
+                                        mention: reply.mention,
+                                    });
 
-                            let _ = current.id().send_message(ctx, |m| m.content(message)).await;
+                                    transfers::spawn_upload(
+                                        Arc::clone(&ctx.cache),
+                                        Arc::clone(&ctx.http),
+                                        current.id(),
+                                        attaching.take().unwrap(),
+                                        caption,
+                                        reply,
+                                        events.sender(),
+                                    );
+                                }
+                                Key::Char('\t') if input.starts_with("/upload ") => {
+                                    let (partial, _) =
+                                        commands::split_word(&input["/upload ".len()..]);
+                                    let (completed, candidates) =
+                                        transfers::complete_path(&partial);
+                                    input.set(format!(
+                                        "/upload {}",
+                                        commands::escape_word(&completed)
+                                    ));
+
+                                    if !candidates.is_empty() {
+                                        *status = Some(Status::Info(candidates.join("  ")));
+                                    }
+                                }
+                                Key::Char('\n') => {
+                                    let message = input.take();
+                                    let reply = replying_to.take();
+
+                                    *recalled = None;
+                                    if let Err(e) = history.push(current.id(), &message) {
+                                        *status = Some(Status::Error(format!(
+                                            "Could not save the input history: {}",
+                                            e
+                                        )));
+                                    }
+
+                                    // `//` starts a message with a `/`
+                                    let message = match message.strip_prefix('/') {
+                                        Some(rest) if rest.starts_with('/') => rest.to_string(),
+                                        _ => message,
+                                    };
+                                    let _ = send_message(ctx, current.id(), message, reply).await;
+                                }
+                                Key::Alt('m') => {
+                                    if let Some(reply) = replying_to {
+                                        reply.mention = !reply.mention;
+                                    }
+                                }
+                                // Up in an empty input edits our last message; once
+                                // Ctrl-P recalled something, it goes further back
+                                Key::Up if input.is_empty() && recalled.is_none() => {
+                                    let last_own_message = messages
+                                        .iter()
+                                        .rev()
+                                        .find(|(message, _)| Some(message.author) == *me);
+
+                                    if let Some((message, _)) = last_own_message {
+                                        input.set(message_text(message).to_string());
+                                        *input_mode = InputMode::EditingMessage {
+                                            message: message.id,
+                                            draft: String::new(),
+                                        };
+                                    }
+                                }
+                                Key::Esc if attaching.is_some() => {
+                                    *attaching = None;
+                                }
+                                Key::Esc if replying_to.is_some() => {
+                                    *replying_to = None;
+                                }
+                                Key::Esc => {
+                                    *input_mode = InputMode::Normal;
+                                }
+                                Key::Ctrl('x') => {
+                                    return Action::ComposeExternally;
+                                }
+                                Key::Ctrl('r') => {
+                                    *recalled = None;
+                                    *input_mode = InputMode::SearchingHistory {
+                                        query: String::new(),
+                                        found: None,
+                                        draft: input.take(),
+                                    };
+                                }
+                                // Up and Down are the editor's until the cursor is on the
+                                // first or last line
+                                _ if !input.handle_key(input_key) => match input_key {
+                                    Key::Up | Key::Ctrl('p') => {
+                                        let sent = history.channel(current.id());
+                                        let older = recalled.as_ref().map_or(0, |(i, _)| i + 1);
+                                        if let Some(text) = sent.get(older) {
+                                            let text = text.to_string();
+                                            let draft = match recalled.take() {
+                                                Some((_, draft)) => draft,
+                                                None => input.take(),
+                                            };
+                                            input.set(text);
+                                            *recalled = Some((older, draft));
+                                        }
+                                    }
+                                    Key::Down | Key::Ctrl('n') => match recalled.take() {
+                                        Some((0, draft)) => input.set(draft),
+                                        Some((i, draft)) => {
+                                            let sent = history.channel(current.id());
+                                            input.set(sent[i - 1].to_string());
+                                            *recalled = Some((i - 1, draft));
+                                        }
+                                        None => {}
+                                    },
+                                    _ => {}
+                                },
+                                _ => {}
+                            },
+                            InputMode::SearchingHistory {
+                                query,
+                                found,
+                                draft,
+                            } => match input_key {
+                                Key::Char('\n') => {
+                                    *input_mode = InputMode::Editing;
+                                }
+                                Key::Esc | Key::Ctrl('g') => {
+                                    input.set(std::mem::take(draft));
+                                    *input_mode = InputMode::Editing;
+                                }
+                                _ => {
+                                    match input_key {
+                                        Key::Ctrl('r') => {
+                                            // the next older match, if there is one
+                                            if let Some(older) = history.search(query, *found) {
+                                                *found = Some(older);
+                                            }
+                                        }
+                                        Key::Backspace => {
+                                            query.pop();
+                                            *found = history.search(query, None);
+                                        }
+                                        Key::Char(c) => {
+                                            query.push(c);
+                                            // the current match is kept while it still matches
+                                            *found = history.search(query, found.map(|i| i + 1));
+                                        }
+                                        _ => {}
+                                    }
+
+                                    match found.and_then(|found| history.get(found)) {
+                                        Some(text) => input.set(text.to_string()),
+                                        None => input.set(draft.clone()),
+                                    }
+                                }
+                            },
+                            InputMode::EditingMessage { message, draft } => match input_key {
+                                Key::Char('\n') => {
+                                    let message_id = *message;
+                                    let content = input.take();
+                                    input.set(std::mem::take(draft));
+                                    *input_mode = InputMode::Editing;
+
+                                    let result = ctx
+                                        .http
+                                        .edit_message(current.id(), message_id, &content)
+                                        .await;
+
+                                    match result {
+                                        Ok(_) => {
+                                            if let Some((message, _)) = messages
+                                                .iter_mut()
+                                                .find(|(m, _)| m.id == message_id)
+                                            {
+                                                message.content = MessageContent::Content(content);
+                                            }
+                                        }
+                                        Err(e) => {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not edit message: {}",
+                                                e
+                                            )));
+                                        }
+                                    }
+                                }
+                                Key::Esc => {
+                                    input.set(std::mem::take(draft));
+                                    *input_mode = InputMode::Editing;
+                                }
+                                Key::Ctrl('x') => {
+                                    return Action::ComposeExternally;
+                                }
+                                _ => {
+                                    input.handle_key(input_key);
+                                }
+                            },
+                            InputMode::Selecting { selected } => match input_key {
+                                Key::Up | Key::Char('k') => {
+                                    *selected = selected.saturating_sub(1);
+                                }
+                                Key::Down | Key::Char('j') => {
+                                    if *selected + 1 < messages.len() {
+                                        *selected += 1;
+                                    }
+                                }
+                                Key::Char('g') => {
+                                    *selected = 0;
+                                }
+                                Key::Char('G') => {
+                                    *selected = messages.len() - 1;
+                                }
+                                Key::Char('\n') => {
+                                    *popup = Some(Popup::MessageActions {
+                                        index: *selected,
+                                        selected: 0,
+                                    });
+                                }
+                                Key::Char('r') => {
+                                    message_action = Some((*selected, MessageAction::Reply));
+                                }
+                                Key::Char('e') => {
+                                    message_action = Some((*selected, MessageAction::Edit));
+                                }
+                                Key::Char('d') => {
+                                    message_action = Some((*selected, MessageAction::Delete));
+                                }
+                                Key::Char('y') => {
+                                    message_action = Some((*selected, MessageAction::CopyText));
+                                }
+                                Key::Esc => {
+                                    *input_mode = InputMode::Normal;
+                                }
+                                _ => {}
+                            },
                         }
-                        Key::Char(c) => {
-                            input.push(c);
+                    }
+
+                    // the candidates follow the word being typed, until it is left
+                    let word = completion
+                        .as_ref()
+                        .map(|completion| (completion.start, completion.word.clone()));
+                    if let Some((start, word)) = word {
+                        let (current_start, current_word) = input.word_before_cursor();
+                        if !matches!(input_mode, InputMode::Editing) || popup.is_some() {
+                            *completion = None;
+                        } else if (current_start, current_word) != (start, word.as_str()) {
+                            *completion = complete(
+                                input,
+                                members.get(&server.id).and_then(Option::as_ref),
+                                messages,
+                                server_channels,
+                                recent_completions,
+                            );
                         }
-                        Key::Backspace => {
-                            input.pop();
+                    }
+
+                    if let Some(commands::Invocation { kind, args }) = command {
+                        let arg = |i: usize| args[i].as_str();
+
+                        match kind {
+                            commands::Kind::Join => {
+                                let name = arg(0).trim_start_matches('#');
+                                let mentioned: Option<ChannelId> = mentioned_id(arg(0), "<#");
+                                let channel =
+                                    server_channels.iter().find(|channel| match mentioned {
+                                        Some(id) => channel.id() == id,
+                                        None => channel
+                                            .name()
+                                            .map_or(false, |it| it.eq_ignore_ascii_case(name)),
+                                    });
+                                match channel {
+                                    Some(channel) => open = Some((channel.id(), None)),
+                                    None => {
+                                        *status = Some(Status::Error(format!(
+                                            "No channel {} here",
+                                            arg(0)
+                                        )));
+                                    }
+                                }
+                            }
+                            commands::Kind::Server => {
+                                let channel = server_list
+                                    .iter()
+                                    .flatten()
+                                    .find(|server| server.name.eq_ignore_ascii_case(arg(0)))
+                                    .and_then(|server| server.channels.first());
+                                match channel {
+                                    Some(channel) => open = Some((*channel, None)),
+                                    None => {
+                                        *status = Some(Status::Error(format!(
+                                            "No server named {}",
+                                            arg(0)
+                                        )));
+                                    }
+                                }
+                            }
+                            commands::Kind::Msg => {
+                                let user = find_user(
+                                    arg(0),
+                                    members.get(&server.id).and_then(Option::as_ref),
+                                    messages,
+                                );
+                                *status = Some(match user {
+                                    Some(user) => match ctx.http.open_dm(user).await {
+                                        Ok(channel) => {
+                                            let text = arg(1).to_string();
+                                            match send_message(ctx, channel.id(), text, None).await
+                                            {
+                                                Ok(()) => {
+                                                    Status::Info(format!("Sent to {}", arg(0)))
+                                                }
+                                                Err(e) => Status::Error(format!(
+                                                    "Could not send the message: {}",
+                                                    e
+                                                )),
+                                            }
+                                        }
+                                        Err(e) => Status::Error(format!(
+                                            "Could not open the conversation: {}",
+                                            e
+                                        )),
+                                    },
+                                    None => Status::Error(format!("No user {} here", arg(0))),
+                                });
+                            }
+                            commands::Kind::Me => {
+                                let action = format!("*{}*", arg(0));
+                                let reply = replying_to.take();
+                                let _ = send_message(ctx, current.id(), action, reply).await;
+                            }
+                            commands::Kind::Shrug => {
+                                let text = format!("{} {}", arg(0), SHRUG).trim_start().to_string();
+                                let reply = replying_to.take();
+                                let _ = send_message(ctx, current.id(), text, reply).await;
+                            }
+                            commands::Kind::Nick => {
+                                *status = Some(match *me {
+                                    Some(me) => {
+                                        match ctx.http.edit_member(server.id, me, arg(0)).await {
+                                            Ok(_) => {
+                                                Status::Info(format!("Nickname set to {}", arg(0)))
+                                            }
+                                            Err(e) => Status::Error(format!(
+                                                "Could not set nickname: {}",
+                                                e
+                                            )),
+                                        }
+                                    }
+                                    None => Status::Error("Not connected yet".to_string()),
+                                });
+                            }
+                            commands::Kind::Status => {
+                                let text = Some(arg(0).to_string()).filter(|text| !text.is_empty());
+                                match set_status(ctx, *presence, text.clone()).await {
+                                    Ok(_) => {
+                                        *status_text = text;
+                                        // the presence is now one the user chose
+                                        *auto_idle = false;
+                                    }
+                                    Err(e) => {
+                                        *status = Some(Status::Error(format!(
+                                            "Could not set status: {}",
+                                            e
+                                        )));
+                                    }
+                                }
+                            }
+                            commands::Kind::Topic => {
+                                let result = ctx
+                                    .http
+                                    .edit_channel_description(current.id(), arg(0))
+                                    .await;
+                                if let Err(e) = result {
+                                    *status =
+                                        Some(Status::Error(format!("Could not set topic: {}", e)));
+                                }
+                            }
+                            commands::Kind::Upload if arg(0).is_empty() => {
+                                let dir = std::env::current_dir().unwrap_or_default();
+                                match transfers::list_dir(&dir) {
+                                    Ok(entries) => {
+                                        *popup = Some(Popup::FilePicker {
+                                            dir,
+                                            entries,
+                                            selected: 0,
+                                        });
+                                    }
+                                    Err(e) => {
+                                        *status = Some(Status::Error(format!(
+                                            "Could not open {}: {}",
+                                            dir.display(),
+                                            e
+                                        )));
+                                    }
+                                }
+                            }
+                            commands::Kind::Upload => {
+                                let reply = replying_to.take().map(|reply| ReplyData {
+                                    id: reply.message,
+                                    mention: reply.mention,
+                                });
+
+                                transfers::spawn_upload(
+                                    Arc::clone(&ctx.cache),
+                                    Arc::clone(&ctx.http),
+                                    current.id(),
+                                    transfers::expand_home(arg(0)),
+                                    arg(1).to_string(),
+                                    reply,
+                                    events.sender(),
+                                );
+                            }
+                            commands::Kind::Quit => {
+                                return Action::Break;
+                            }
+                            commands::Kind::Help => {
+                                *status = Some(match arg(0).trim_start_matches('/') {
+                                    "" => {
+                                        let names: Vec<String> = commands::COMMANDS
+                                            .iter()
+                                            .map(|command| format!("/{}", command.name))
+                                            .collect();
+                                        Status::Info(format!(
+                                            "{}  (/help <command> for more)",
+                                            names.join(" ")
+                                        ))
+                                    }
+                                    name => match commands::find(name) {
+                                        Some(command) => Status::Info(format!(
+                                            "{} — {}",
+                                            command.usage(),
+                                            command.help
+                                        )),
+                                        None => Status::Error(format!(
+                                            "Unknown command /{}, see /help",
+                                            name
+                                        )),
+                                    },
+                                });
+                            }
                         }
-                        Key::Esc => {
-                            *input_mode = InputMode::Normal;
+                    }
+
+                    if let Some((index, action)) = message_action {
+                        let (message, author) = &messages[index];
+
+                        match action {
+                            MessageAction::Reply => {
+                                *replying_to = Some(Reply {
+                                    message: message.id,
+                                    author_name: author.display_name().to_string(),
+                                    mention: true,
+                                });
+                                *input_mode = InputMode::Editing;
+                            }
+                            MessageAction::Edit => {
+                                if Some(message.author) == *me {
+                                    *input_mode = InputMode::EditingMessage {
+                                        message: message.id,
+                                        draft: input.take(),
+                                    };
+                                    input.set(message_text(message).to_string());
+                                } else {
+                                    *status = Some(Status::Error(
+                                        "You can only edit your own messages".to_string(),
+                                    ));
+                                }
+                            }
+                            MessageAction::Delete => {
+                                let allowed = match *me {
+                                    Some(me) if me == message.author => true,
+                                    Some(me) => can_manage_messages(ctx, server, me).await,
+                                    None => false,
+                                };
+
+                                if allowed {
+                                    *popup = Some(Popup::ConfirmDelete { index });
+                                } else {
+                                    *status = Some(Status::Error(
+                                        "You are not allowed to delete this message".to_string(),
+                                    ));
+                                }
+                            }
+                            MessageAction::CopyText => {
+                                if let Err(e) = util::copy_to_clipboard(message_text(message)) {
+                                    *status =
+                                        Some(Status::Error(format!("Could not copy text: {}", e)));
+                                }
+                            }
+                            MessageAction::CopyLink => {
+                                let link = format!(
+                                    "{}/server/{}/channel/{}/{}",
+                                    APP_URL, server.id, message.channel, message.id
+                                );
+                                if let Err(e) = util::copy_to_clipboard(&link) {
+                                    *status =
+                                        Some(Status::Error(format!("Could not copy link: {}", e)));
+                                }
+                            }
+                            MessageAction::Attachments => {
+                                if message.attachments.iter().flatten().next().is_some() {
+                                    *popup = Some(Popup::Attachments { index, selected: 0 });
+                                } else {
+                                    *status = Some(Status::Error(
+                                        "This message has no attachments".to_string(),
+                                    ));
+                                }
+                            }
+                            MessageAction::ViewAuthor => {
+                                *popup = Some(profile_popup(
+                                    ctx,
+                                    images,
+                                    events,
+                                    profiles,
+                                    author.clone(),
+                                ));
+                            }
+                            MessageAction::JumpToReferenced => {
+                                let referenced =
+                                    message.replies.iter().flatten().next().and_then(|id| {
+                                        messages.iter().position(|(m, _)| m.id == *id)
+                                    });
+
+                                match referenced {
+                                    Some(referenced) => {
+                                        *input_mode = InputMode::Selecting {
+                                            selected: referenced,
+                                        };
+                                    }
+                                    None => {
+                                        *status = Some(Status::Error(
+                                            "The referenced message is not loaded".to_string(),
+                                        ));
+                                    }
+                                }
+                            }
+                            MessageAction::JoinInvite => {
+                                match embeds::invite_codes(message_text(message)).first() {
+                                    Some(code) => match ctx.http.join_invite(code).await {
+                                        Ok(_) => {
+                                            let name = match invites.get(*code) {
+                                                Some(embeds::InviteState::Loaded(invite)) => {
+                                                    invite.server_name.as_str()
+                                                }
+                                                _ => "the server",
+                                            };
+                                            *status =
+                                                Some(Status::Info(format!("Joined {}", name)));
+                                        }
+                                        Err(e) => {
+                                            *status = Some(Status::Error(format!(
+                                                "Could not join: {}",
+                                                e
+                                            )));
+                                        }
+                                    },
+                                    None => {
+                                        *status = Some(Status::Error(
+                                            "This message has no invite".to_string(),
+                                        ));
+                                    }
+                                }
+                            }
                         }
-                        _ => {}
-                    },
-                },
+                    }
+
+                    update_typing(
+                        events,
+                        current.id(),
+                        typing_sent,
+                        composing(input_mode, input),
+                    );
+                }
                 Event::RobespierreEvent(ev) => match ev {
+                    ServerToClientEvent::ChannelStartTyping { id, user } => {
+                        if current.id() == id && Some(user) != *me {
+                            match typing.iter_mut().find(|(typer, _, _)| *typer == user) {
+                                Some((_, _, last_seen)) => *last_seen = Instant::now(),
+                                None => {
+                                    let name = match user.user(ctx).await {
+                                        Ok(user) => user.username,
+                                        Err(_) => "Someone".to_string(),
+                                    };
+                                    typing.push((user, name, Instant::now()));
+                                }
+                            }
+                        }
+                    }
+                    ServerToClientEvent::ChannelStopTyping { id, user } => {
+                        if current.id() == id {
+                            typing.retain(|(typer, _, _)| *typer != user);
+                        }
+                    }
                     ServerToClientEvent::Message { message } => {
+                        if Some(message.author) == *me {
+                            // sending a message marks the channel as read
+                            unreads.ack(message.channel, message.id);
+                        } else {
+                            let mentions_us = me.map_or(false, |me| {
+                                message.mentions.iter().flatten().any(|user| *user == me)
+                            });
+                            unreads.message(message.channel, message.id, mentions_us);
+
+                            if let Some(me) = *me {
+                                spawn_check_mention(
+                                    ctx,
+                                    events,
+                                    &message,
+                                    me,
+                                    messages,
+                                    referenced_messages,
+                                );
+                            }
+                        }
+
                         if current.id() == message.channel {
+                            // a message ends the typing
+                            typing.retain(|(typer, _, _)| *typer != message.author);
+
+                            load_message_extras(
+                                ctx,
+                                images,
+                                events,
+                                &message,
+                                messages,
+                                referenced_messages,
+                                invites,
+                            )
+                            .await;
+
                             let user_opt_member =
                                 message.author_user_opt_member(ctx).await.unwrap();
                             messages.push((message, user_opt_member));
                         }
                     }
+                    ServerToClientEvent::MessageUpdate { id, channel, data } => {
+                        if current.id() == channel {
+                            if let Some(index) = messages.iter().position(|(m, _)| m.id == id) {
+                                let message = &mut messages[index].0;
+                                if let Some(content) = data.content {
+                                    message.content = content;
+                                }
+                                if let Some(embeds) = data.embeds {
+                                    message.embeds = Some(embeds);
+                                }
+
+                                // embeds are usually added after the message is sent
+                                let message = message.clone();
+                                load_message_extras(
+                                    ctx,
+                                    images,
+                                    events,
+                                    &message,
+                                    messages,
+                                    referenced_messages,
+                                    invites,
+                                )
+                                .await;
+                            }
+                        }
+                    }
+                    ServerToClientEvent::MessageDelete { id, channel } => {
+                        if current.id() == channel {
+                            if let Some(index) = messages.iter().position(|(m, _)| m.id == id) {
+                                messages.remove(index);
+                                // popups about a message follow it, or close with it
+                                if let Some(
+                                    Popup::ConfirmDelete { index: popup_index }
+                                    | Popup::MessageActions {
+                                        index: popup_index, ..
+                                    }
+                                    | Popup::Attachments {
+                                        index: popup_index, ..
+                                    },
+                                ) = popup
+                                {
+                                    if *popup_index == index {
+                                        *popup = None;
+                                    } else if *popup_index > index {
+                                        *popup_index -= 1;
+                                    }
+                                }
+                                if let InputMode::Selecting { selected } = input_mode {
+                                    if *selected > index {
+                                        *selected -= 1;
+                                    }
+                                }
+                                clamp_selection(input_mode, messages.len());
+                            }
+                        }
+                    }
                     ServerToClientEvent::Ready { event } => {
+                        let user = event
+                            .users
+                            .iter()
+                            .find(|user| user.relationship == Some(RelationshipStatus::User));
+                        *me = user.map(|user| user.id);
+                        if let Some(status) = user.and_then(|user| user.status.as_ref()) {
+                            *presence = status.presence.unwrap_or(Presence::Online);
+                            *status_text = status.text.clone();
+                        }
                         *server_list = Some(event.servers);
+                        *direct_messages =
+                            direct::DirectMessages::new(&event.channels, &event.users, *me);
+
+                        unreads.set_last_messages(&event.channels);
+                        let http = Arc::clone(&ctx.http);
+                        let tx = events.sender();
+                        tokio::spawn(async move {
+                            let fetched = http.fetch_unreads().await;
+                            let _ =
+                                tx.send(Event::UnreadsLoaded(fetched.map_err(|e| e.to_string())));
+                        });
+
+                        for (message, _) in messages.iter() {
+                            request_images(images, events, message);
+                        }
+                    }
+                    ServerToClientEvent::UserUpdate { id, data, clear } => {
+                        if let Some(online) = data.online {
+                            for member_list in members.values_mut().flatten() {
+                                member_list.set_online(id, online);
+                            }
+                            direct_messages.set_online(id, online);
+                        }
+                        if let Some(status) = &data.status {
+                            for member_list in members.values_mut().flatten() {
+                                member_list.set_presence(id, status.presence);
+                            }
+                            direct_messages.set_presence(id, status.presence);
+                        }
+
+                        // changed from another session
+                        if Some(id) == *me {
+                            if let Some(status) = data.status {
+                                let changed = status.presence.unwrap_or(Presence::Online);
+                                // not the echo of us becoming Idle
+                                if changed != *presence {
+                                    *auto_idle = false;
+                                }
+                                *presence = changed;
+                                *status_text = status.text;
+                            }
+                            if clear == Some(UserField::StatusText) {
+                                *status_text = None;
+                            }
+                        }
+                    }
+                    ServerToClientEvent::ChannelAck {
+                        id,
+                        user,
+                        message_id,
+                    } => {
+                        // acknowledged from another session
+                        if Some(user) == *me {
+                            unreads.ack(id, message_id);
+                        }
                     }
                     _ => {}
                 },
-                Event::Tick => {}
+                Event::MessageDeleteFailed { message, error } => {
+                    if current.id() == message.0.channel {
+                        // other messages may have come or gone since, so it
+                        // goes back in id order
+                        let index = messages.partition_point(|(m, _)| m.id < message.0.id);
+                        messages.insert(index, *message);
+
+                        // what points at the messages after it follows them
+                        if let Some(
+                            Popup::ConfirmDelete { index: popup_index }
+                            | Popup::MessageActions {
+                                index: popup_index, ..
+                            }
+                            | Popup::Attachments {
+                                index: popup_index, ..
+                            },
+                        ) = popup
+                        {
+                            if *popup_index >= index {
+                                *popup_index += 1;
+                            }
+                        }
+                        if let InputMode::Selecting { selected } = input_mode {
+                            if *selected >= index {
+                                *selected += 1;
+                            }
+                        }
+                    }
+                    *status = Some(Status::Error(format!(
+                        "Could not delete message: {}",
+                        error
+                    )));
+                }
+                Event::Download {
+                    file,
+                    status: progress,
+                } => {
+                    *status = Some(match progress {
+                        TransferStatus::InProgress { done, total } => Status::Info(match total {
+                            Some(total) => format!(
+                                "Downloading {}: {}% ({} / {})",
+                                file,
+                                done * 100 / total.max(1),
+                                util::format_size(done),
+                                util::format_size(total)
+                            ),
+                            None => format!("Downloading {}: {}", file, util::format_size(done)),
+                        }),
+                        TransferStatus::Done(path) => {
+                            Status::Info(format!("Downloaded {} to {}", file, path.display()))
+                        }
+                        TransferStatus::Failed(error) => {
+                            Status::Error(format!("Could not download {}: {}", file, error))
+                        }
+                    });
+                }
+                Event::Upload {
+                    file,
+                    status: progress,
+                } => {
+                    *status = Some(match progress {
+                        TransferStatus::InProgress { done, total } => Status::Info(match total {
+                            Some(total) => format!(
+                                "Uploading {}: {}% ({} / {})",
+                                file,
+                                done * 100 / total.max(1),
+                                util::format_size(done),
+                                util::format_size(total)
+                            ),
+                            None => format!("Uploading {}: {}", file, util::format_size(done)),
+                        }),
+                        TransferStatus::Done(_) => Status::Info(format!("Uploaded {}", file)),
+                        TransferStatus::Failed(error) => {
+                            Status::Error(format!("Could not upload {}: {}", file, error))
+                        }
+                    });
+                }
+                Event::Paste(text) => {
+                    note_activity(ctx, last_input, auto_idle, presence, status_text);
+
+                    if popup.is_none() {
+                        if let InputMode::Normal = input_mode {
+                            *input_mode = InputMode::Editing;
+                        }
+                        if let InputMode::Editing | InputMode::EditingMessage { .. } = input_mode {
+                            // terminals send Enter as \r, also inside pastes
+                            input.insert_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+                        }
+                        update_typing(
+                            events,
+                            current.id(),
+                            typing_sent,
+                            composing(input_mode, input),
+                        );
+                    }
+                }
+                Event::Mentioned(mention) => {
+                    inbox.push(*mention);
+                }
+                Event::ImageLoaded { url, image } => {
+                    images.insert(url, image);
+                }
+                Event::ProfileLoaded { user, profile } => match profile {
+                    Ok((bio, mutual_servers)) => {
+                        profiles.insert(
+                            user,
+                            Some(profile::Profile {
+                                bio,
+                                mutual_servers,
+                            }),
+                        );
+                    }
+                    Err(e) => {
+                        // fetched again the next time it is opened
+                        profiles.remove(&user);
+                        *status = Some(Status::Error(format!("Could not fetch profile: {}", e)));
+                    }
+                },
+                Event::MembersLoaded {
+                    server: server_id,
+                    members: fetched,
+                } => match fetched {
+                    Ok(fetched) if server_id == server.id => {
+                        let member_list = members::MemberList::new(server, fetched);
+                        members.insert(server_id, Some(member_list));
+                    }
+                    Ok(_) => {
+                        // the roles of that server are not at hand, fetch
+                        // again when it is opened
+                        members.remove(&server_id);
+                    }
+                    Err(e) => {
+                        members.remove(&server_id);
+                        *show_members = false;
+                        *status = Some(Status::Error(format!("Could not fetch members: {}", e)));
+                    }
+                },
+                Event::UnreadsLoaded(fetched) => match fetched {
+                    Ok(channel_unreads) => {
+                        for unread in channel_unreads {
+                            unreads.set_read(
+                                unread.id.channel,
+                                unread.last_id,
+                                unread.mentions.unwrap_or_default(),
+                            );
+                        }
+                        unreads.set_loaded();
+
+                        // the history was loaded before knowing what was read
+                        *first_unread = find_first_unread(messages, current.id(), unreads);
+                    }
+                    Err(e) => {
+                        *status = Some(Status::Error(format!(
+                            "Could not fetch unread messages: {}",
+                            e
+                        )));
+                    }
+                },
+                // only the last channel asked for is switched to
+                Event::ChannelLoaded { id, .. } if *opening != Some(id) => {}
+                Event::ChannelLoaded {
+                    select, channel, ..
+                } => {
+                    *opening = None;
+                    match channel {
+                        Ok(loaded) => switch_to = Some((loaded, select)),
+                        Err(e) => {
+                            *status = Some(Status::Error(format!("Could not open channel: {}", e)));
+                        }
+                    }
+                }
+                Event::Tick => {
+                    typing.retain(|(_, _, last_seen)| last_seen.elapsed() < TYPING_TIMEOUT);
+
+                    // saved as it is typed, in case we crash
+                    if let Err(e) =
+                        drafts.set(current.id(), unsent_text(input, input_mode, recalled))
+                    {
+                        *status = Some(Status::Error(format!("Could not save the draft: {}", e)));
+                    }
+
+                    let idle = config
+                        .idle_after
+                        .map_or(false, |idle_after| last_input.elapsed() >= idle_after);
+                    // a presence chosen by the user is left alone
+                    if idle && *presence == Presence::Online && me.is_some() {
+                        *auto_idle = true;
+                        *presence = Presence::Idle;
+                        spawn_set_status(ctx, Presence::Idle, status_text.clone());
+                    }
+
+                    // the latest messages are on screen unless scrolled up to older ones
+                    let at_bottom = match input_mode {
+                        InputMode::Selecting { selected } => *selected + 1 >= messages.len(),
+                        _ => true,
+                    };
+                    let last_message = messages.last().map(|(message, _)| message.id);
+
+                    // nothing is acked before knowing what was read, as
+                    // that would hide what came since
+                    if let Some(last_message) = last_message.filter(|_| unreads.is_loaded()) {
+                        let channel = current.id();
+                        let read = unreads
+                            .last_read(channel)
+                            .map_or(false, |last_read| last_read >= last_message);
+
+                        if at_bottom && !read {
+                            unreads.ack(channel, last_message);
+
+                            let http = Arc::clone(&ctx.http);
+                            tokio::spawn(async move {
+                                let _ = http.ack_message(channel, last_message).await;
+                            });
+                        }
+                    }
+                }
             },
         }
+
+        let AppStateInternal::ServerChannel { server, popup, .. } = state;
+        let wants_members = *show_members || matches!(popup, Some(Popup::ServerInfo { .. }));
+        if wants_members && !members.contains_key(&server.id) {
+            // fetched when first shown, as this is slow for large servers
+            members.insert(server.id, None);
+
+            let server_id = server.id;
+            let http = Arc::clone(&ctx.http);
+            let tx = events.sender();
+            tokio::spawn(async move {
+                let fetched = http.fetch_members(server_id).await;
+                let _ = tx.send(Event::MembersLoaded {
+                    server: server_id,
+                    members: fetched.map_err(|e| e.to_string()),
+                });
+            });
+        }
+
+        // evicted images that came back on screen
+        images.request_wanted(&events.sender());
+
+        if let Some((channel, select)) = open {
+            *opening = Some(channel);
+            spawn_load_channel(ctx, events, channel, select);
+
+            let AppStateInternal::ServerChannel { status, .. } = state;
+            *status = Some(Status::Info("Opening channel…".to_string()));
+        }
+
+        if let Some((loaded, select)) = switch_to {
+            let AppStateInternal::ServerChannel {
+                input,
+                recalled,
+                input_mode,
+                typing_sent,
+                current_channel,
+                ..
+            } = state;
+            let draft_error = drafts
+                .set(
+                    current_channel.id(),
+                    unsent_text(input, input_mode, recalled),
+                )
+                .err();
+            update_typing(events, current_channel.id(), typing_sent, false);
+
+            let channel = loaded.channel.id();
+            *state = AppStateInternal::new(*loaded, unreads);
+
+            let AppStateInternal::ServerChannel {
+                input,
+                messages,
+                input_mode,
+                status,
+                ..
+            } = state;
+
+            if let Some(draft) = drafts.get(channel) {
+                input.set(draft.to_string());
+            }
+            if let Some(e) = draft_error {
+                *status = Some(Status::Error(format!("Could not save the draft: {}", e)));
+            }
+
+            if let Some(select) = select {
+                match messages.iter().position(|(m, _)| m.id == select) {
+                    Some(index) => *input_mode = InputMode::Selecting { selected: index },
+                    None => {
+                        *status = Some(Status::Error(
+                            "The message is too old to be loaded".to_string(),
+                        ));
+                    }
+                }
+            }
+
+            for (message, _) in messages.iter() {
+                request_images(images, events, message);
+            }
+        }
     }
 
     Action::None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete_input(text: &str) -> Option<completion::Completion> {
+        let mut input = editor::Editor::default();
+        input.set(text.to_string());
+        complete(&input, None, &[], &[], &[])
+    }
+
+    #[test]
+    fn completes_emoji_shortcodes() {
+        let completion = complete_input("nice :tad").unwrap();
+        assert_eq!(completion.start, 5);
+        assert_eq!(completion.word, ":tad");
+        assert_eq!(completion.candidates[0].replacement, "🎉");
+    }
+
+    #[test]
+    fn completes_commands_only_at_the_start() {
+        let completion = complete_input("/hel").unwrap();
+        assert_eq!(completion.candidates[0].replacement, "/help ");
+        assert!(complete_input("see /hel").is_none());
+    }
+
+    #[test]
+    fn leaves_other_words_alone() {
+        assert!(complete_input("hello").is_none());
+        assert!(complete_input("").is_none());
+        assert!(complete_input(":zzzz").is_none());
+    }
+
+    #[test]
+    fn accepting_replaces_the_word() {
+        let mut input = editor::Editor::default();
+        input.set("hi :wav".to_string());
+        let completion = complete(&input, None, &[], &[], &[]).unwrap();
+        let mut recent = Vec::new();
+        accept_completion(&mut input, completion, &mut recent);
+
+        assert_eq!(&*input, "hi 👋");
+        assert_eq!(recent, vec!["👋".to_string()]);
+    }
+}
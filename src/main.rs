@@ -4,27 +4,52 @@ use robespierre::{
     robespierre_models::id::ChannelId,
     Authentication,
 };
-use std::{error::Error, io, sync::Arc};
-use termion::{input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
+use std::{
+    error::Error,
+    io::{self, Stdout, Write},
+    sync::Arc,
+    time::Duration,
+};
+use termion::{
+    input::MouseTerminal,
+    raw::{IntoRawMode, RawTerminal},
+    screen::AlternateScreen,
+};
 use tui::{backend::TermionBackend, Terminal};
 
 use tui_revolt::{
     util::event::{Config, Events},
-    Action, AppState, OpenAt,
+    Action, AppConfig, AppState, OpenAt,
 };
 
-async fn main_impl() -> Result<(), Box<dyn Error>> {
-    let token = std::env::var("TOKEN")
-        .expect("Cannot get token; set environment variable TOKEN=... and run again");
-
-    let auth = Authentication::user(token);
+type AppTerminal = Terminal<TermionBackend<AlternateScreen<MouseTerminal<RawTerminal<Stdout>>>>>;
 
-    // Terminal initialization
+fn setup_terminal() -> io::Result<AppTerminal> {
     let stdout = io::stdout().into_raw_mode()?;
     let stdout = MouseTerminal::from(stdout);
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
+    // so pasted text can be told apart from typed text
+    write!(terminal.backend_mut(), "\x1b[?2004h")?;
+
+    Ok(terminal)
+}
+
+/// Gives the terminal back as it was before `setup_terminal`
+fn restore_terminal(mut terminal: AppTerminal) -> io::Result<()> {
+    write!(terminal.backend_mut(), "\x1b[?2004l")?;
+    // the rest is undone when dropped
+    Ok(())
+}
+
+async fn main_impl() -> Result<(), Box<dyn Error>> {
+    let token = std::env::var("TOKEN")
+        .expect("Cannot get token; set environment variable TOKEN=... and run again");
+
+    let auth = Authentication::user(token);
+
+    let mut terminal = setup_terminal()?;
 
     // Setup event handlers and the robespierre connection
     let cache = Cache::new(CacheConfig::default());
@@ -32,10 +57,27 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
 
     let http = Arc::new(Http::new(&auth).await?);
 
+    let mut app_config = AppConfig::default();
+    if let Ok(download_dir) = std::env::var("DOWNLOAD_DIR") {
+        app_config.download_dir = download_dir.into();
+    }
+    if std::env::var("IMAGE_PREVIEWS").as_deref() == Ok("0") {
+        app_config.image_previews = false;
+    }
+    if let Ok(idle_after) = std::env::var("IDLE_AFTER") {
+        // in seconds, 0 to never become idle
+        app_config.idle_after = match idle_after.parse() {
+            Ok(0) => None,
+            Ok(secs) => Some(Duration::from_secs(secs)),
+            Err(_) => app_config.idle_after,
+        };
+    }
+
     // Create new app state
     let mut app = AppState::new(
         cache,
         http,
+        app_config,
         OpenAt::Channel("01F7ZSBSFHCAAJQ92ZGTY67HMN".parse::<ChannelId>().unwrap()),
     )
     .await?;
@@ -43,14 +85,23 @@ async fn main_impl() -> Result<(), Box<dyn Error>> {
     loop {
         // Draw UI
         terminal.draw(|f| tui_revolt::render(&app, f))?;
+        tui_revolt::draw_graphics(&mut app, &mut terminal)?;
 
         match tui_revolt::update(&mut app, &mut events).await {
             Action::Break => break,
+            Action::ComposeExternally => {
+                events.pause_input();
+                restore_terminal(terminal)?;
+                tui_revolt::compose_externally(&mut app);
+                terminal = setup_terminal()?;
+                events.resume_input();
+            }
             Action::None => {}
         }
     }
 
     events.abort_tasks();
+    restore_terminal(terminal)?;
 
     Ok(())
 }
@@ -0,0 +1,136 @@
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+/// Inline markers and the modifier they toggle, longest first so `**` is
+/// not taken for two `*`
+const MARKERS: [(&str, Modifier); 5] = [
+    ("**", Modifier::BOLD),
+    ("~~", Modifier::CROSSED_OUT),
+    ("__", Modifier::UNDERLINED),
+    ("*", Modifier::ITALIC),
+    ("_", Modifier::ITALIC),
+];
+
+/// Renders the subset of markdown Revolt descriptions and bios use:
+/// headings, lists, quotes, code blocks and inline styles.
+pub fn render(text: &str) -> Vec<Spans<'static>> {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+
+        if in_code_block {
+            lines.push(Spans::from(Span::styled(
+                format!("  {}", line),
+                Style::default().fg(Color::Yellow),
+            )));
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        let heading_level = trimmed.chars().take_while(|c| *c == '#').count();
+
+        let spans = if (1..=6).contains(&heading_level) && trimmed[heading_level..].starts_with(' ')
+        {
+            let style = Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            inline(trimmed[heading_level..].trim(), style)
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let style = Style::default().fg(Color::Gray);
+            let mut spans = vec![Span::styled("│ ", Style::default().fg(Color::DarkGray))];
+            spans.extend(inline(quote.trim_start(), style));
+            spans
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            let indent = " ".repeat(line.len() - trimmed.len());
+            let mut spans = vec![Span::raw(format!("{}• ", indent))];
+            spans.extend(inline(item, Style::default()));
+            spans
+        } else {
+            inline(line, Style::default())
+        };
+
+        lines.push(Spans::from(spans));
+    }
+
+    lines
+}
+
+/// Splits a line into spans styled by its inline markup, on top of `base`
+fn inline(text: &str, base: Style) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut modifiers = Modifier::empty();
+    let mut rest = text;
+
+    let flush = |current: &mut String, spans: &mut Vec<Span<'static>>, modifiers: Modifier| {
+        if !current.is_empty() {
+            spans.push(Span::styled(
+                std::mem::take(current),
+                base.add_modifier(modifiers),
+            ));
+        }
+    };
+
+    while let Some(c) = rest.chars().next() {
+        // inline code is taken as is
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                flush(&mut current, &mut spans, modifiers);
+                spans.push(Span::styled(
+                    rest[1..end + 1].to_string(),
+                    base.fg(Color::Yellow),
+                ));
+                rest = &rest[end + 2..];
+                continue;
+            }
+        }
+
+        // [text](url)
+        if c == '[' {
+            let link = rest.find("](").and_then(|middle| {
+                let end = rest[middle..].find(')')? + middle;
+                Some((&rest[1..middle], &rest[middle + 2..end], end))
+            });
+            if let Some((label, url, end)) = link {
+                flush(&mut current, &mut spans, modifiers);
+                spans.push(Span::styled(
+                    label.to_string(),
+                    base.add_modifier(modifiers | Modifier::UNDERLINED)
+                        .fg(Color::LightBlue),
+                ));
+                spans.push(Span::styled(
+                    format!(" ({})", url),
+                    base.fg(Color::DarkGray),
+                ));
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        if let Some((marker, modifier)) =
+            MARKERS.iter().find(|(marker, _)| rest.starts_with(marker))
+        {
+            // only toggle if it is closed later, or closes an open one
+            if modifiers.contains(*modifier) || rest[marker.len()..].contains(marker) {
+                flush(&mut current, &mut spans, modifiers);
+                modifiers.toggle(*modifier);
+                rest = &rest[marker.len()..];
+                continue;
+            }
+        }
+
+        current.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    flush(&mut current, &mut spans, modifiers);
+    spans
+}
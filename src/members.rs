@@ -0,0 +1,276 @@
+use std::{cell::Cell, collections::HashMap};
+
+use robespierre::robespierre_models::{
+    id::{RoleId, UserId},
+    servers::{FetchMembersResult, Server},
+    users::Presence,
+};
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::ListItem,
+};
+
+use crate::{presence, util};
+
+/// A member of the server, as shown in the member list
+struct Entry {
+    user: UserId,
+    /// The nickname, or the username if there is none
+    name: String,
+    online: bool,
+    presence: Option<Presence>,
+    /// Index in `MemberList::groups` of the hoisted role they are listed under
+    group: Option<usize>,
+    /// The colour of their highest ranked coloured role
+    color: Option<Color>,
+}
+
+enum Row {
+    /// A group header, e.g. `Moderators — 3`
+    Header(String),
+    /// The member at this index in `MemberList::members`
+    Member(usize),
+}
+
+/// The members of a server, grouped like the official client does: online
+/// members under their highest hoisted role, then the other online
+/// members, then everyone offline.
+pub struct MemberList {
+    /// The names of the hoisted roles, highest ranked first
+    groups: Vec<String>,
+    members: Vec<Entry>,
+    /// What is drawn, one line each
+    rows: Vec<Row>,
+    /// The member whose profile can be opened
+    selected: Option<UserId>,
+    /// The first row on screen, moved by `visible_items` to keep the
+    /// selected member in view
+    offset: Cell<usize>,
+}
+
+impl MemberList {
+    pub fn new(server: &Server, fetched: FetchMembersResult) -> Self {
+        let mut roles: Vec<_> = server.roles.iter().flatten().collect();
+        roles.sort_by_key(|(_, role)| role.rank);
+
+        let hoisted: Vec<&RoleId> = roles
+            .iter()
+            .filter(|(_, role)| role.hoist)
+            .map(|(id, _)| *id)
+            .collect();
+        let groups = roles
+            .iter()
+            .filter(|(_, role)| role.hoist)
+            .map(|(_, role)| role.name.clone())
+            .collect();
+
+        let users: HashMap<UserId, _> = fetched
+            .users
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect();
+
+        let members = fetched
+            .members
+            .into_iter()
+            .filter_map(|member| {
+                let user = users.get(&member.id.user)?;
+                let member_roles = member.roles.unwrap_or_default();
+
+                // `roles` is sorted, so the first match is the highest ranked
+                let group = hoisted.iter().position(|id| member_roles.contains(id));
+                let color = roles
+                    .iter()
+                    .filter(|(id, _)| member_roles.contains(id))
+                    .find_map(|(_, role)| role.colour.as_deref().and_then(util::hex_color));
+
+                Some(Entry {
+                    user: user.id,
+                    name: member.nickname.unwrap_or_else(|| user.username.clone()),
+                    online: user.online.unwrap_or(false),
+                    presence: user.status.as_ref().and_then(|status| status.presence),
+                    group,
+                    color,
+                })
+            })
+            .collect();
+
+        let mut list = Self {
+            groups,
+            members,
+            rows: Vec::new(),
+            selected: None,
+            offset: Cell::new(0),
+        };
+        list.regroup();
+        list
+    }
+
+    /// Recomputes `rows` after the members changed
+    fn regroup(&mut self) {
+        let mut order: Vec<usize> = (0..self.members.len()).collect();
+        order.sort_by_cached_key(|&i| self.members[i].name.to_lowercase());
+
+        let mut rows = Vec::with_capacity(self.members.len() + self.groups.len() + 2);
+        let mut push_group = |name: &str, members: Vec<usize>| {
+            if !members.is_empty() {
+                rows.push(Row::Header(format!("{} — {}", name, members.len())));
+                rows.extend(members.into_iter().map(Row::Member));
+            }
+        };
+
+        for (group, name) in self.groups.iter().enumerate() {
+            let members = order
+                .iter()
+                .copied()
+                .filter(|&i| self.members[i].online && self.members[i].group == Some(group))
+                .collect();
+            push_group(name, members);
+        }
+
+        let online = order
+            .iter()
+            .copied()
+            .filter(|&i| self.members[i].online && self.members[i].group.is_none())
+            .collect();
+        push_group("Online", online);
+
+        let offline = order
+            .iter()
+            .copied()
+            .filter(|&i| !self.members[i].online)
+            .collect();
+        push_group("Offline", offline);
+
+        self.rows = rows;
+    }
+
+    /// Updates whether `user` is online, if they are a member
+    pub fn set_online(&mut self, user: UserId, online: bool) {
+        let mut changed = false;
+        for entry in self.members.iter_mut().filter(|entry| entry.user == user) {
+            changed |= entry.online != online;
+            entry.online = online;
+        }
+
+        if changed {
+            self.regroup();
+        }
+    }
+
+    /// Updates the presence `user` chose, if they are a member
+    pub fn set_presence(&mut self, user: UserId, presence: Option<Presence>) {
+        for entry in self.members.iter_mut().filter(|entry| entry.user == user) {
+            entry.presence = presence;
+        }
+    }
+
+    /// Every member, with the name they are listed under
+    pub fn names(&self) -> impl Iterator<Item = (UserId, &str)> {
+        self.members
+            .iter()
+            .map(|entry| (entry.user, entry.name.as_str()))
+    }
+
+    pub fn member_count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// The row of the selected member
+    fn selected_row(&self) -> Option<usize> {
+        let selected = self.selected?;
+        self.rows
+            .iter()
+            .position(|row| matches!(row, Row::Member(i) if self.members[*i].user == selected))
+    }
+
+    /// Moves the selection `delta` members down, or up if negative,
+    /// selecting the first member if there is no selection yet
+    pub fn select_by(&mut self, delta: isize) {
+        let member_rows: Vec<usize> = self
+            .rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Member(i) => Some(*i),
+                Row::Header(_) => None,
+            })
+            .collect();
+        if member_rows.is_empty() {
+            return;
+        }
+
+        let current = self.selected.and_then(|selected| {
+            member_rows
+                .iter()
+                .position(|i| self.members[*i].user == selected)
+        });
+        let next = match current {
+            Some(current) => (current as isize + delta).clamp(0, member_rows.len() as isize - 1),
+            None => 0,
+        };
+
+        self.selected = Some(self.members[member_rows[next as usize]].user);
+    }
+
+    pub fn selected(&self) -> Option<UserId> {
+        self.selected
+    }
+
+    /// The rows that fit in `height` lines, from the scroll position.
+    ///
+    /// Only these are turned into list items, so large servers stay cheap
+    /// to draw.
+    pub fn visible_items(&self, height: usize) -> Vec<ListItem<'_>> {
+        let mut start = self.offset.get();
+        if let Some(row) = self.selected_row() {
+            if row < start {
+                start = row;
+            } else if row >= start + height {
+                start = row + 1 - height;
+            }
+        }
+        let start = start.min(self.rows.len().saturating_sub(height));
+        self.offset.set(start);
+        let selected_row = self.selected_row();
+
+        self.rows
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(height)
+            .map(|(i, row)| match row {
+                Row::Header(header) => ListItem::new(Span::styled(
+                    header.as_str(),
+                    Style::default()
+                        .fg(Color::Gray)
+                        .add_modifier(Modifier::BOLD),
+                )),
+                Row::Member(member) => {
+                    let entry = &self.members[*member];
+                    let dot = presence::color(entry.online, entry.presence);
+                    let name_style = if entry.online {
+                        Style::default()
+                    } else {
+                        Style::default().fg(Color::DarkGray)
+                    };
+                    let name_style = match entry.color {
+                        Some(color) if entry.online => name_style.fg(color),
+                        _ => name_style,
+                    };
+
+                    let name_style = if Some(i) == selected_row {
+                        name_style.add_modifier(Modifier::REVERSED)
+                    } else {
+                        name_style
+                    };
+
+                    ListItem::new(Spans::from(vec![
+                        Span::styled("● ", Style::default().fg(dot)),
+                        Span::styled(entry.name.as_str(), name_style),
+                    ]))
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,34 @@
+use robespierre::robespierre_models::users::Presence;
+use tui::style::Color;
+
+/// The presences we can choose from, in the order they are offered
+pub const CHOICES: [Presence; 4] = [
+    Presence::Online,
+    Presence::Idle,
+    Presence::Busy,
+    Presence::Invisible,
+];
+
+pub fn label(presence: Presence) -> &'static str {
+    match presence {
+        Presence::Online => "Online",
+        Presence::Idle => "Idle",
+        Presence::Busy => "Busy",
+        Presence::Invisible => "Invisible",
+    }
+}
+
+/// The colour of the dot drawn next to a user. Invisible users look
+/// offline, and online users without a presence look online.
+pub fn color(online: bool, presence: Option<Presence>) -> Color {
+    if !online {
+        return Color::DarkGray;
+    }
+
+    match presence.unwrap_or(Presence::Online) {
+        Presence::Online => Color::Green,
+        Presence::Idle => Color::Yellow,
+        Presence::Busy => Color::Red,
+        Presence::Invisible => Color::DarkGray,
+    }
+}
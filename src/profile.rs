@@ -0,0 +1,141 @@
+use robespierre::{
+    model::user_opt_member::UserOptMember,
+    robespierre_models::{
+        id::ServerId,
+        servers::Server,
+        users::{Badges, Presence},
+    },
+};
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+use crate::{presence, util};
+
+/// The badges and how they are called in the official client
+const BADGES: [(Badges, &str); 6] = [
+    (Badges::DEVELOPER, "Developer"),
+    (Badges::TRANSLATOR, "Translator"),
+    (Badges::SUPPORTER, "Supporter"),
+    (Badges::RESPONSIBLE_DISCLOSURE, "Responsible disclosure"),
+    (Badges::REVOLT_TEAM, "Revolt team"),
+    (Badges::EARLY_ADOPTER, "Early adopter"),
+];
+
+/// What is fetched from the API about a user when their profile is opened
+pub struct Profile {
+    /// The "About me" text
+    pub bio: Option<String>,
+    pub mutual_servers: Vec<ServerId>,
+}
+
+/// The lines of the profile popup of `user`, below their avatar.
+///
+/// `profile` is `None` while it is being fetched.
+pub fn lines(
+    user: &UserOptMember,
+    profile: Option<&Profile>,
+    server: &Server,
+    servers: &[Server],
+) -> Vec<Spans<'static>> {
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            user.display_name().to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(Span::raw(format!("@{}", user.user.username))),
+    ];
+
+    let online = user.user.online.unwrap_or(false);
+    let status = user.user.status.as_ref();
+    let chosen = status.and_then(|status| status.presence);
+    let label = match chosen {
+        Some(chosen) if online => presence::label(chosen),
+        _ if online => presence::label(Presence::Online),
+        _ => "Offline",
+    };
+    let mut status_line = vec![
+        Span::styled("● ", Style::default().fg(presence::color(online, chosen))),
+        Span::raw(label),
+    ];
+    if let Some(text) = status.and_then(|status| status.text.as_ref()) {
+        status_line.push(Span::raw(format!(" · {}", text)));
+    }
+    lines.push(Spans::from(status_line));
+
+    if let Some(badges) = user.user.badges {
+        let names: Vec<&str> = BADGES
+            .iter()
+            .filter(|(badge, _)| badges.contains(*badge))
+            .map(|(_, name)| *name)
+            .collect();
+        if !names.is_empty() {
+            lines.push(Spans::from(Span::styled(
+                names.join(" · "),
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+    }
+
+    let roles = user
+        .member
+        .as_ref()
+        .and_then(|member| member.roles.as_ref())
+        .into_iter()
+        .flatten()
+        .filter_map(|id| server.roles.as_ref()?.get(id))
+        .collect::<Vec<_>>();
+    if !roles.is_empty() {
+        lines.push(Spans::from(Span::raw("")));
+        lines.push(util::heading("Roles"));
+
+        let mut spans = Vec::new();
+        for role in roles {
+            let color = role
+                .colour
+                .as_deref()
+                .and_then(util::hex_color)
+                .unwrap_or(Color::Gray);
+            spans.push(Span::styled("● ", Style::default().fg(color)));
+            spans.push(Span::raw(format!("{}  ", role.name)));
+        }
+        lines.push(Spans::from(spans));
+    }
+
+    match profile {
+        Some(profile) => {
+            if let Some(bio) = profile.bio.as_ref().filter(|bio| !bio.trim().is_empty()) {
+                lines.push(Spans::from(Span::raw("")));
+                lines.push(util::heading("About me"));
+                lines.extend(
+                    bio.lines()
+                        .map(|line| Spans::from(Span::raw(line.to_string()))),
+                );
+            }
+
+            let mutual: Vec<&str> = servers
+                .iter()
+                .filter(|server| profile.mutual_servers.contains(&server.id))
+                .map(|server| server.name.as_str())
+                .collect();
+            if !mutual.is_empty() {
+                lines.push(Spans::from(Span::raw("")));
+                lines.push(util::heading("Mutual servers"));
+                lines.push(Spans::from(Span::raw(mutual.join(", "))));
+            }
+        }
+        None => lines.push(Spans::from(Span::styled(
+            "Loading profile…",
+            Style::default().fg(Color::DarkGray),
+        ))),
+    }
+
+    lines.push(Spans::from(Span::raw("")));
+    lines.push(Spans::from(Span::styled(
+        "[m]essage  [f]riend request  [b]lock",
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+
+    lines
+}
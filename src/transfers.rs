@@ -0,0 +1,383 @@
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use reqwest::{
+    multipart::{Form, Part},
+    Body,
+};
+use robespierre::{
+    model::ChannelIdExt,
+    robespierre_cache::Cache,
+    robespierre_http::Http,
+    robespierre_models::{
+        channels::ReplyData,
+        id::{AttachmentId, ChannelId},
+    },
+};
+use serde::Deserialize;
+use termion::event::Key;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc::UnboundedSender,
+};
+
+use crate::{
+    util::{
+        event::{Event, TransferStatus},
+        CH,
+    },
+    AUTUMN_URL,
+};
+
+type TransferResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// How often progress is reported at most, when the percentage done
+/// doesn't change
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Decides which progress updates are worth an event: one per percent,
+/// or one every `PROGRESS_INTERVAL`, rather than one per chunk.
+struct Progress {
+    percent: Option<u64>,
+    sent: Instant,
+}
+
+impl Progress {
+    fn new() -> Self {
+        Self {
+            percent: None,
+            sent: Instant::now(),
+        }
+    }
+
+    fn should_send(&mut self, done: u64, total: Option<u64>) -> bool {
+        let percent = total
+            .filter(|total| *total > 0)
+            .map(|total| done * 100 / total);
+
+        if percent == self.percent && self.sent.elapsed() < PROGRESS_INTERVAL {
+            return false;
+        }
+
+        self.percent = percent;
+        self.sent = Instant::now();
+        true
+    }
+}
+
+/// Downloads `url` to `dir/filename` in the background, reporting
+/// progress through `tx` as `Event::Download`s. An existing file is
+/// never overwritten, `name (1).ext` and so on are tried instead.
+pub fn spawn_download(url: String, filename: &str, dir: PathBuf, tx: UnboundedSender<Event<Key>>) {
+    // never trust the file name to not contain `..` or `/`
+    let filename = Path::new(filename)
+        .file_name()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "attachment".to_string());
+
+    tokio::spawn(async move {
+        let status = match download(&url, &dir, &filename, &tx).await {
+            Ok(path) => TransferStatus::Done(path),
+            Err(e) => TransferStatus::Failed(e.to_string()),
+        };
+
+        let _ = tx.send(Event::Download {
+            file: filename,
+            status,
+        });
+    });
+}
+
+async fn download(
+    url: &str,
+    dir: &Path,
+    filename: &str,
+    tx: &UnboundedSender<Event<Key>>,
+) -> TransferResult<PathBuf> {
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    let total = response.content_length();
+
+    tokio::fs::create_dir_all(dir).await?;
+    let (path, mut file) = create_new_file(dir, filename).await?;
+
+    let result = async {
+        let mut done = 0;
+        let mut progress = Progress::new();
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            done += chunk.len() as u64;
+
+            if progress.should_send(done, total) {
+                let _ = tx.send(Event::Download {
+                    file: filename.to_string(),
+                    status: TransferStatus::InProgress { done, total },
+                });
+            }
+        }
+
+        file.flush().await?;
+
+        TransferResult::Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok(path),
+        Err(e) => {
+            // don't leave half a file behind
+            drop(file);
+            let _ = tokio::fs::remove_file(&path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Creates `dir/filename`, or `dir/name (1).ext` and so on if it exists
+async fn create_new_file(dir: &Path, filename: &str) -> io::Result<(PathBuf, tokio::fs::File)> {
+    let name = Path::new(filename);
+    let stem = name.file_stem().map_or_else(
+        || filename.to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let extension = name
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()));
+
+    let mut n = 0;
+    loop {
+        let path = match n {
+            0 => dir.join(filename),
+            n => dir.join(format!(
+                "{} ({}){}",
+                stem,
+                n,
+                extension.as_deref().unwrap_or_default()
+            )),
+        };
+
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AutumnResponse {
+    id: AttachmentId,
+}
+
+/// Uploads the file at `path` to autumn in the background, then sends a
+/// message to `channel` with it attached and `caption` as content.
+/// Progress is reported through `tx` as `Event::Upload`s.
+pub fn spawn_upload(
+    cache: Arc<Cache>,
+    http: Arc<Http>,
+    channel: ChannelId,
+    path: PathBuf,
+    caption: String,
+    reply: Option<ReplyData>,
+    tx: UnboundedSender<Event<Key>>,
+) {
+    let filename = path
+        .file_name()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    tokio::spawn(async move {
+        let result = async {
+            let id = upload(&path, &filename, &tx).await?;
+
+            channel
+                .send_message(&CH(&cache, &http), |m| {
+                    let m = m.content(caption).attachment(id);
+                    match reply {
+                        Some(reply) => m.reply(reply),
+                        None => m,
+                    }
+                })
+                .await?;
+
+            TransferResult::Ok(())
+        }
+        .await;
+
+        let status = match result {
+            Ok(()) => TransferStatus::Done(path),
+            Err(e) => TransferStatus::Failed(e.to_string()),
+        };
+
+        let _ = tx.send(Event::Upload {
+            file: filename,
+            status,
+        });
+    });
+}
+
+async fn upload(
+    path: &Path,
+    filename: &str,
+    tx: &UnboundedSender<Event<Key>>,
+) -> TransferResult<AttachmentId> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    let file = tokio::fs::File::open(path).await?;
+    let total = file.metadata().await?.len();
+
+    // read the file as reqwest pulls the chunks out of the stream, and
+    // report progress as they are read; the stream ends after an error
+    let progress_tx = tx.clone();
+    let progress_filename = filename.to_string();
+    let state = Some((file, 0, Progress::new()));
+    let stream = futures::stream::unfold(state, move |state| {
+        let tx = progress_tx.clone();
+        let filename = progress_filename.clone();
+        async move {
+            let (mut file, done, mut progress) = state?;
+
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = match file.read(&mut chunk).await {
+                Ok(0) => return None,
+                Ok(read) => read,
+                Err(e) => return Some((Err(e), None)),
+            };
+            chunk.truncate(read);
+
+            let done = done + read as u64;
+            if progress.should_send(done, Some(total)) {
+                let _ = tx.send(Event::Upload {
+                    file: filename,
+                    status: TransferStatus::InProgress {
+                        done,
+                        total: Some(total),
+                    },
+                });
+            }
+
+            Some((Ok(chunk), Some((file, done, progress))))
+        }
+    });
+
+    let part =
+        Part::stream_with_length(Body::wrap_stream(stream), total).file_name(filename.to_string());
+    let form = Form::new().part("file", part);
+
+    let response: AutumnResponse = reqwest::Client::new()
+        .post(format!("{}/attachments", AUTUMN_URL))
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response.id)
+}
+
+pub struct FileEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// The entries of `dir`, directories first, both sorted by name,
+/// without hidden files and with a `..` entry at the top.
+pub fn list_dir(dir: &Path) -> std::io::Result<Vec<FileEntry>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let is_dir = entry.file_type()?.is_dir();
+        entries.push(FileEntry { name, is_dir });
+    }
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    if dir.parent().is_some() {
+        entries.insert(
+            0,
+            FileEntry {
+                name: "..".to_string(),
+                is_dir: true,
+            },
+        );
+    }
+
+    Ok(entries)
+}
+
+/// Expands a leading `~` or `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    // `~user` is someone else's home, which is left alone
+    let rest = path
+        .strip_prefix('~')
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'));
+
+    match (rest, std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(format!("{}{}", home.to_string_lossy(), rest)),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Completes the path `partial` as far as it is unambiguous.
+///
+/// Returns the completed path, and all the file names that matched
+/// if there was more than one.
+pub fn complete_path(partial: &str) -> (String, Vec<String>) {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(slash) => (&partial[..=slash], &partial[slash + 1..]),
+        None => ("", partial),
+    };
+
+    let lookup_dir = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir)
+    };
+
+    let mut matches: Vec<FileEntry> = match list_dir(&lookup_dir) {
+        Ok(entries) => entries
+            .into_iter()
+            .filter(|entry| entry.name != ".." && entry.name.starts_with(prefix))
+            .collect(),
+        Err(_) => return (partial.to_string(), Vec::new()),
+    };
+
+    match matches.len() {
+        0 => (partial.to_string(), Vec::new()),
+        1 => {
+            let entry = matches.remove(0);
+            let suffix = if entry.is_dir { "/" } else { "" };
+            (format!("{}{}{}", dir, entry.name, suffix), Vec::new())
+        }
+        _ => {
+            let mut common = matches[0].name.clone();
+            for entry in &matches[1..] {
+                let len = common
+                    .char_indices()
+                    .zip(entry.name.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map_or(0, |((i, a), _)| i + a.len_utf8());
+                common.truncate(len);
+            }
+
+            let names = matches.into_iter().map(|entry| entry.name).collect();
+            (format!("{}{}", dir, common), names)
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use robespierre::robespierre_models::{
+    channels::Channel,
+    id::{ChannelId, MessageId},
+    servers::Server,
+};
+
+/// What we know about a channel being read
+#[derive(Default)]
+struct ReadState {
+    /// The last message we have read
+    last_read: Option<MessageId>,
+    /// The last message sent in the channel
+    last_message: Option<MessageId>,
+    /// Unread messages mentioning us
+    mentions: Vec<MessageId>,
+}
+
+impl ReadState {
+    fn is_unread(&self) -> bool {
+        match (self.last_read, self.last_message) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(last_read), Some(last_message)) => last_message > last_read,
+        }
+    }
+}
+
+/// The read state of all the channels we know of, kept up to date from
+/// the event stream.
+#[derive(Default)]
+pub struct Unreads {
+    channels: HashMap<ChannelId, ReadState>,
+    /// Whether what we have read was fetched, before which nothing can be
+    /// told apart from being read
+    loaded: bool,
+}
+
+impl Unreads {
+    /// Initializes the last messages of `channels`, as received in `Ready`
+    pub fn set_last_messages(&mut self, channels: &[Channel]) {
+        for channel in channels {
+            if let Channel::TextChannel {
+                id,
+                last_message: Some(last_message),
+                ..
+            } = channel
+            {
+                self.channels.entry(*id).or_default().last_message = Some(*last_message);
+            }
+        }
+    }
+
+    /// Initializes what we have read in `channel`, as received from the
+    /// unreads API
+    pub fn set_read(
+        &mut self,
+        channel: ChannelId,
+        last_read: Option<MessageId>,
+        mentions: Vec<MessageId>,
+    ) {
+        let state = self.channels.entry(channel).or_default();
+        state.last_read = last_read;
+        state.mentions = mentions;
+    }
+
+    /// Records that all the channels were initialized with `set_read`
+    pub fn set_loaded(&mut self) {
+        self.loaded = true;
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Records a new message in `channel`
+    pub fn message(&mut self, channel: ChannelId, message: MessageId, mentions_us: bool) {
+        let state = self.channels.entry(channel).or_default();
+        state.last_message = Some(message);
+        if mentions_us {
+            state.mentions.push(message);
+        }
+    }
+
+    /// Marks everything up to and including `message` in `channel` as read
+    pub fn ack(&mut self, channel: ChannelId, message: MessageId) {
+        let state = self.channels.entry(channel).or_default();
+        if state
+            .last_read
+            .map_or(true, |last_read| message > last_read)
+        {
+            state.last_read = Some(message);
+        }
+        if state
+            .last_message
+            .map_or(true, |last_message| message > last_message)
+        {
+            state.last_message = Some(message);
+        }
+        state.mentions.retain(|mention| *mention > message);
+    }
+
+    pub fn last_read(&self, channel: ChannelId) -> Option<MessageId> {
+        self.channels
+            .get(&channel)
+            .and_then(|state| state.last_read)
+    }
+
+    pub fn is_unread(&self, channel: ChannelId) -> bool {
+        self.channels
+            .get(&channel)
+            .map_or(false, ReadState::is_unread)
+    }
+
+    pub fn mention_count(&self, channel: ChannelId) -> usize {
+        self.channels
+            .get(&channel)
+            .map_or(0, |state| state.mentions.len())
+    }
+
+    pub fn is_server_unread(&self, server: &Server) -> bool {
+        server
+            .channels
+            .iter()
+            .any(|channel| self.is_unread(*channel))
+    }
+
+    /// The first unread channel after `current`, going through the
+    /// channels of `servers` in order and wrapping around
+    pub fn next_unread(&self, servers: &[Server], current: ChannelId) -> Option<ChannelId> {
+        let channels: Vec<ChannelId> = servers
+            .iter()
+            .flat_map(|server| server.channels.iter().copied())
+            .collect();
+        let start = channels
+            .iter()
+            .position(|channel| *channel == current)
+            .map_or(0, |position| position + 1);
+
+        channels[start..]
+            .iter()
+            .chain(&channels[..start])
+            .copied()
+            .find(|channel| *channel != current && self.is_unread(*channel))
+    }
+
+    pub fn server_mention_count(&self, server: &Server) -> usize {
+        server
+            .channels
+            .iter()
+            .map(|channel| self.mention_count(*channel))
+            .sum()
+    }
+}
+
+/// The text shown before the name of a channel or server in lists:
+/// the number of mentions, or a dot if there is anything unread.
+pub fn badge(unread: bool, mentions: usize) -> String {
+    match (unread, mentions) {
+        (_, mentions) if mentions > 0 => format!("({}) ", mentions),
+        (true, _) => "● ".to_string(),
+        (false, _) => "  ".to_string(),
+    }
+}
@@ -1,10 +1,20 @@
 pub mod event;
 
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
 use rand::distributions::{Distribution, Uniform};
 use rand::rngs::ThreadRng;
 use robespierre::robespierre_cache::{Cache, HasCache};
 use robespierre::robespierre_http::{HasHttp, Http};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
 use tui::widgets::ListState;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Clone)]
 pub struct RandomSignal {
@@ -131,6 +141,116 @@ impl<T> StatefulList<T> {
     }
 }
 
+/// A rectangle of `percent_x` by `percent_y` of `r`, centered inside `r`
+pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_y) / 2),
+                Constraint::Percentage(percent_y),
+                Constraint::Percentage((100 - percent_y) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(popup_layout[1])[1]
+}
+
+/// Where the items of a `List` drawn from the bottom left corner of `area`
+/// end up, as the row of their first line, or `None` if they are not drawn.
+///
+/// This mirrors the scrolling `List` does when rendered with a fresh
+/// `ListState` that has `selected` selected.
+pub fn list_item_tops(heights: &[usize], selected: Option<usize>, area: Rect) -> Vec<Option<u16>> {
+    let mut tops = vec![None; heights.len()];
+    if heights.is_empty() {
+        return tops;
+    }
+
+    let max_height = area.height as usize;
+    let (mut start, mut end, mut height) = (0, 0, 0);
+    for item_height in heights {
+        if height + item_height > max_height {
+            break;
+        }
+        height += item_height;
+        end += 1;
+    }
+
+    let selected = selected.unwrap_or(0).min(heights.len() - 1);
+    while selected >= end {
+        height += heights[end];
+        end += 1;
+        while height > max_height {
+            height -= heights[start];
+            start += 1;
+        }
+    }
+
+    let mut current_height = 0;
+    for (top, item_height) in tops[start..end].iter_mut().zip(&heights[start..end]) {
+        current_height += *item_height as u16;
+        *top = Some(area.bottom() - current_height);
+    }
+
+    tops
+}
+
+/// Puts `text` in the system clipboard through the OSC 52 escape sequence,
+/// which the terminal emulator forwards to the clipboard.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64::encode(text))?;
+    stdout.flush()
+}
+
+/// Opens `url` in the default application, without waiting for it.
+pub fn open_url(url: &str) -> io::Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+/// Formats a number of bytes in a human readable way, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
 pub struct CH<'a>(pub &'a Cache, pub &'a Http);
 
 impl<'a> HasCache for CH<'a> {
@@ -144,3 +264,111 @@ impl<'a> HasHttp for CH<'a> {
         self.1
     }
 }
+
+/// Parses a `#rrggbb` colour, as used by roles and embeds
+pub fn hex_color(colour: &str) -> Option<Color> {
+    colour
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .map(|rgb| Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+/// The title of a section of a popup
+pub fn heading(text: &'static str) -> Spans<'static> {
+    Spans::from(Span::styled(
+        text,
+        Style::default()
+            .fg(Color::Gray)
+            .add_modifier(Modifier::BOLD),
+    ))
+}
+
+/// How many rows `lines` take once wrapped at spaces to `width` columns,
+/// like a `Paragraph` draws them
+pub fn wrapped_height(lines: &[Spans], width: u16) -> u16 {
+    let width = (width as usize).max(1);
+    let rows: usize = lines
+        .iter()
+        .map(|line| {
+            let text: String = line.0.iter().map(|span| span.content.as_ref()).collect();
+            let mut rows = 1;
+            let mut column = 0;
+            for word in text.split(' ') {
+                let word_width = word.width();
+                if column > 0 && column + 1 + word_width <= width {
+                    column += 1 + word_width;
+                    continue;
+                }
+                if column > 0 {
+                    rows += 1;
+                }
+                // words longer than a row are cut
+                rows += word_width.saturating_sub(1) / width;
+                column = match word_width {
+                    0 => 0,
+                    _ => (word_width - 1) % width + 1,
+                };
+            }
+            rows
+        })
+        .sum();
+    rows.min(u16::MAX as usize) as u16
+}
+
+/// Replaces the file at `path` with `contents` in one go, through a
+/// temporary file next to it, so that it is never left half written.
+///
+/// Only we can read it, as it holds what we typed, DMs included.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    // left over by a crash, maybe with other permissions
+    let _ = fs::remove_file(&temp);
+    let created = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp);
+    let written = created.and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    match written.and_then(|()| fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Escapes backslashes and newlines, so `text` fits on one line of a file
+pub fn escape_line(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Undoes `escape_line`
+pub fn unescape_line(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
@@ -1,27 +1,140 @@
-use std::io;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use image::RgbaImage;
+use robespierre::model::user_opt_member::UserOptMember;
 use robespierre::robespierre_cache::{Cache, CommitToCache};
 use robespierre::robespierre_events::Connection;
-use robespierre::robespierre_models::events::ServerToClientEvent;
+use robespierre::robespierre_models::channels::{ChannelUnread, Message};
+use robespierre::robespierre_models::events::{ClientToServerEvent, ServerToClientEvent};
+use robespierre::robespierre_models::id::{ChannelId, MessageId, ServerId, UserId};
+use robespierre::robespierre_models::servers::FetchMembersResult;
 use robespierre::Authentication;
-use termion::event::Key;
+use termion::event::{Event as TermEvent, Key};
 use termion::input::TermRead;
 
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::task::JoinHandle;
 
+use crate::inbox::Mention;
+use crate::LoadedChannel;
+
+/// How often paused input checks whether it was resumed
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What terminals send around pasted text when bracketed paste is on
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
 pub enum Event<I> {
     Input(I),
+    /// Text pasted in the terminal, in one piece
+    Paste(String),
     RobespierreEvent(ServerToClientEvent),
+    /// Deleting a message failed; it should be put back in the message
+    /// list.
+    MessageDeleteFailed {
+        message: Box<(Message, UserOptMember)>,
+        error: String,
+    },
+    Download {
+        file: String,
+        status: TransferStatus,
+    },
+    Upload {
+        file: String,
+        status: TransferStatus,
+    },
+    /// An image requested for previewing was downloaded and decoded
+    ImageLoaded {
+        url: String,
+        image: Result<RgbaImage, String>,
+    },
+    /// The members of `server` were fetched for the member list
+    MembersLoaded {
+        server: ServerId,
+        members: Result<FetchMembersResult, String>,
+    },
+    /// The profile of `user` was fetched: their bio and the servers we
+    /// have in common
+    ProfileLoaded {
+        user: UserId,
+        profile: Result<(Option<String>, Vec<ServerId>), String>,
+    },
+    /// The channel `id` to switch to was loaded, `select` being the
+    /// message to select in it
+    ChannelLoaded {
+        id: ChannelId,
+        select: Option<MessageId>,
+        channel: Result<Box<LoadedChannel>, String>,
+    },
+    /// A message mentioning us or replying to us was received
+    Mentioned(Box<Mention>),
+    /// What was read in each channel was fetched, once connected
+    UnreadsLoaded(Result<Vec<ChannelUnread>, String>),
     Tick,
 }
 
+pub enum TransferStatus {
+    /// `done` bytes out of `total`, if the size is known
+    InProgress {
+        done: u64,
+        total: Option<u64>,
+    },
+    /// Finished; the path of the file on disk
+    Done(PathBuf),
+    Failed(String),
+}
+
+/// Whether `file` has something to read within `timeout`
+fn readable(file: &File, timeout: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fd` is one valid pollfd, and 1 is the number passed
+    unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+/// The terminal, read without ever blocking for long, so that reading can
+/// be paused while another program uses the terminal
+struct Input {
+    tty: File,
+    paused: Arc<AtomicBool>,
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.paused.load(Ordering::SeqCst) {
+                std::thread::sleep(INPUT_POLL_INTERVAL);
+                continue;
+            }
+
+            // checked again after waiting, so what is typed once paused is
+            // left to the other program
+            if readable(&self.tty, INPUT_POLL_INTERVAL) && !self.paused.load(Ordering::SeqCst) {
+                return self.tty.read(buf);
+            }
+        }
+    }
+}
+
 /// A small event handler that wrap termion input and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
     rx: UnboundedReceiver<Event<Key>>,
+    tx: UnboundedSender<Event<Key>>,
+    /// Events to send through the robespierre connection
+    client_event_tx: UnboundedSender<ClientToServerEvent>,
+    /// Set while another program reads the terminal
+    input_paused: Arc<AtomicBool>,
     input_handle: JoinHandle<()>,
     tick_handle: JoinHandle<()>,
     robespierre_event_handle: JoinHandle<()>,
@@ -46,16 +159,41 @@ impl Config {
 impl Events {
     pub fn with_config(config: Config, cache: Arc<Cache>) -> Events {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let input_paused = Arc::new(AtomicBool::new(false));
         let input_handle = {
             let tx = tx.clone();
+            let paused = Arc::clone(&input_paused);
             tokio::task::spawn_blocking(move || {
-                let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
+                let tty = match termion::get_tty() {
+                    Ok(tty) => tty,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                };
+                let input = Input { tty, paused };
+                // the text pasted so far, while in a bracketed paste
+                let mut pasted: Option<String> = None;
+                for evt in input.events() {
+                    let event = match (evt, &mut pasted) {
+                        (Ok(TermEvent::Unsupported(bytes)), _) if bytes == PASTE_START => {
+                            pasted = Some(String::new());
+                            continue;
                         }
+                        (Ok(TermEvent::Unsupported(bytes)), Some(_)) if bytes == PASTE_END => {
+                            Event::Paste(pasted.take().unwrap_or_default())
+                        }
+                        (Ok(TermEvent::Key(Key::Char(c))), Some(text)) => {
+                            text.push(c);
+                            continue;
+                        }
+                        (Ok(TermEvent::Key(key)), None) => Event::Input(key),
+                        _ => continue,
+                    };
+
+                    if let Err(err) = tx.send(event) {
+                        eprintln!("{}", err);
+                        return;
                     }
                 }
             })
@@ -74,7 +212,9 @@ impl Events {
             })
         };
 
+        let (client_event_tx, mut client_event_rx) = tokio::sync::mpsc::unbounded_channel();
         let robespierre_event_handle = {
+            let tx = tx.clone();
             tokio::spawn(async move {
                 let auth = config.auth;
 
@@ -87,7 +227,17 @@ impl Events {
                 };
 
                 loop {
-                    let event = match connection.next().await {
+                    let event = tokio::select! {
+                        event = connection.next() => event,
+                        Some(client_event) = client_event_rx.recv() => {
+                            // only typing is sent this way, which isn't worth
+                            // an error drawn over the interface
+                            let _ = connection.send_event(client_event).await;
+                            continue;
+                        }
+                    };
+
+                    let event = match event {
                         Ok(ev) => ev,
                         Err(e) => {
                             eprintln!("{}", e);
@@ -107,6 +257,9 @@ impl Events {
 
         Events {
             rx,
+            tx,
+            client_event_tx,
+            input_paused,
             input_handle,
             tick_handle,
             robespierre_event_handle,
@@ -119,6 +272,25 @@ impl Events {
         self.robespierre_event_handle.abort();
     }
 
+    /// Stops reading the terminal, so another program can
+    pub fn pause_input(&self) {
+        self.input_paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume_input(&self) {
+        self.input_paused.store(false, Ordering::SeqCst);
+    }
+
+    /// A sender for background tasks to report back to the main loop
+    pub fn sender(&self) -> UnboundedSender<Event<Key>> {
+        self.tx.clone()
+    }
+
+    /// Sends `event` through the robespierre connection
+    pub fn send_client_event(&self, event: ClientToServerEvent) {
+        let _ = self.client_event_tx.send(event);
+    }
+
     pub async fn next(&mut self) -> Option<Event<Key>> {
         self.rx.recv().await
     }